@@ -1,9 +1,22 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
 use regex::Regex;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::io::{BufRead, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::Duration;
 use anyhow::{Result, Context};
-use chrono::Local;
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+
+/// 过期时间在注释中的记录格式（UTC）
+const EXPIRES_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
 
 #[derive(Parser)]
 #[command(name = "hostm")]
@@ -11,192 +24,5456 @@ use chrono::Local;
 #[command(version)]
 #[command(propagate_version = true)]
 struct Args {
-    /// hosts 文件路径，默认为 /etc/hosts
-    #[arg(short = 'f', long, default_value = "/etc/hosts")]
-    hosts_file: String,
+    /// hosts 文件路径，默认自动查找系统 hosts 文件；search/list 可重复指定以合并查看多个文件
+    #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+    hosts_file: Vec<PathBuf>,
 
     /// 输出详细日志
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 
+    /// 写入前需要备份 hosts 文件的操作，逗号分隔；none 表示不备份
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Operation::Update, Operation::Delete])]
+    backup: Vec<Operation>,
+
+    /// 备份后若同一 hosts 文件的全部备份超过该字节数，从最旧的开始删除，最新的备份总会保留
+    #[arg(long, value_name = "BYTES")]
+    max_backups_size: Option<u64>,
+
+    /// 网络操作（--resolve 的 DNS 查询）的超时时间，单位毫秒
+    #[arg(long, global = true, value_name = "MS", default_value_t = 5000)]
+    timeout: u64,
+
+    /// 写入前重新读取 hosts 文件，若内容在读取后被修改则放弃写入
+    #[arg(long, default_value_t = false)]
+    check_mtime: bool,
+
+    /// 目标文件看起来不像 hosts 文件时仍然修改
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// 写入时合并连续的空行并去掉文件末尾多余的空行；默认原样保留未改动的空行
+    #[arg(long, default_value_t = false)]
+    normalize_blanks: bool,
+
+    /// 写入时按最长的 IP 对齐所有映射行的域名列
+    #[arg(long, default_value_t = false)]
+    align: bool,
+
+    /// 写入时不对齐映射行（默认）
+    #[arg(long, conflicts_with = "align", default_value_t = false)]
+    no_align: bool,
+
+    /// 写入时文件末尾换行的处理方式
+    #[arg(long, value_enum, default_value_t = TrailingNewline::Keep)]
+    trailing_newline: TrailingNewline,
+
+    /// 写入时将映射行中的 IPv6 地址改写为规范的小写压缩形式，如 2001:0DB8::0001 -> 2001:db8::1
+    #[arg(long, default_value_t = false)]
+    normalize_ipv6: bool,
+
+    /// 自动生成注释的模板，支持 {action}、{date}、{datetime}、{user} 变量，如 "managed by hostm on {date}"
+    #[arg(long, value_name = "TEMPLATE")]
+    comment_format: Option<String>,
+
+    /// 修改类命令写入前要求 hosts 文件当前内容的 SHA-256 与该值一致，否则放弃写入
+    #[arg(long, value_name = "SHA256")]
+    expected_checksum: Option<String>,
+
+    /// 何时使用彩色输出
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// 禁用彩色输出，等同于 --color never
+    #[arg(long, conflicts_with = "color", default_value_t = false)]
+    no_color: bool,
+
+    /// 只读命令（search/list/export/checksum）的结果输出文件，`-` 表示标准输出
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// 以追加方式写入 --output 指定的文件
+    #[arg(long, requires = "output", default_value_t = false)]
+    append: bool,
+
+    /// 修改类命令将结果写入该文件，hosts 文件本身保持不变
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    output_file: Option<PathBuf>,
+
+    /// 修改类命令将修改后的完整内容输出到标准输出，不写入文件；状态信息改为输出到标准错误
+    #[arg(long, global = true, default_value_t = false)]
+    output_only: bool,
+
+    /// 修改类命令写入后，将每处变更追加记录到该文件；未指定时使用环境变量 HOSTM_AUDIT_LOG
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    audit_log: Option<PathBuf>,
+
+    /// 允许修改或删除 127.0.0.1/::1 的 localhost 映射（默认拒绝）
+    #[arg(long, global = true, default_value_t = false)]
+    allow_localhost_edit: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// 写入时文件末尾换行的处理方式
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum TrailingNewline {
+    /// 与修改后的内容保持一致
+    Keep,
+    /// 确保以换行结尾
+    Add,
+    /// 去掉末尾的换行
+    Remove,
+}
+
+/// 何时输出 ANSI 颜色
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ColorChoice {
+    /// 仅当标准输出是终端且未设置 NO_COLOR 时
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// 结合 stream 的终端检测判断是否启用颜色
+    fn enabled(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                // 遵循 no-color.org 约定：NO_COLOR 非空时禁用颜色，--color always 仍可强制启用
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color
+                    && stream.is_terminal()
+                    && std::env::var("TERM").map_or(true, |term| term != "dumb")
+            }
+        }
+    }
+}
+
+/// 会修改 hosts 文件的操作
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Operation {
+    Create,
+    Update,
+    Delete,
+    /// 不对任何操作备份
+    None,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// 更新已存在的域名映射
+    /// 更新已存在的域名映射；域名有 IPv4 和 IPv6 两行（双栈）时只更新与新 IP 同一地址族的那一行
     Update {
         /// 域名
         domain: String,
         /// 新的 IP 地址
-        ip: String,
+        #[arg(required_unless_present_any = ["resolve", "comment_only", "stdin_ip"])]
+        ip: Option<String>,
+        /// 从标准输入读取 IP 地址，如 `terraform output -raw ip | hostm update foo.local --stdin-ip`
+        #[arg(long, conflicts_with_all = ["ip", "resolve", "comment_only"], default_value_t = false)]
+        stdin_ip: bool,
+        /// 通过 DNS 解析域名，使用当前解析结果作为 IP
+        #[arg(long, conflicts_with = "ip", default_value_t = false)]
+        resolve: bool,
+        /// 解析时优先使用 IPv4 地址
+        #[arg(long, conflicts_with = "ip", default_value_t = false)]
+        prefer_ipv4: bool,
+        /// 解析时优先使用 IPv6 地址
+        #[arg(long, conflicts_with_all = ["ip", "prefer_ipv4"], default_value_t = false)]
+        prefer_ipv6: bool,
+        /// 未找到域名时，解释包含该域名的行为何未被匹配
+        #[arg(long, default_value_t = false)]
+        explain: bool,
+        /// 匹配到多行时，操作第 n 行候选（从 1 开始）；未指定时交互选择，非交互时报错并列出候选
+        #[arg(long, value_name = "N")]
+        select: Option<usize>,
+        /// 注释中署名的用户或服务，默认为 $USER
+        #[arg(long)]
+        user: Option<String>,
+        /// 新的行尾注释，需与 --comment-only 一起使用
+        #[arg(long, requires = "comment_only")]
+        comment: Option<String>,
+        /// 只替换注释，不修改 IP
+        #[arg(long, requires = "comment", conflicts_with_all = ["ip", "resolve"], default_value_t = false)]
+        comment_only: bool,
+        /// 仅当域名当前的 IP 与之相同时才更新，否则以状态码 5 退出
+        #[arg(long, value_name = "CURRENT_IP")]
+        if_ip_matches: Option<IpAddr>,
+        /// 为映射设置分组标签（记录在注释中）；未指定时保留原有标签
+        #[arg(long, value_parser = parse_tag, conflicts_with = "comment_only")]
+        tag: Option<String>,
+        /// 只逐行说明将要进行的修改，不写入文件
+        #[arg(long, default_value_t = false)]
+        plan: bool,
     },
     /// 删除域名映射
     Delete {
-        /// 要删除的域名
-        domain: String,
+        /// 要删除的域名，与 --cidr/--tag 同时使用时只删除范围内的映射，与 --alias 同时使用时只处理该规范名的行
+        #[arg(required_unless_present_any = ["cidr", "alias", "tag"])]
+        domain: Option<String>,
+        /// 只删除 IP 位于该网段内的映射，如 10.0.0.0/8；未指定域名时删除网段内全部映射
+        #[arg(long, visible_alias = "scope", value_parser = parse_cidr)]
+        cidr: Option<Cidr>,
+        /// 未找到域名时，解释包含该域名的行为何未被匹配
+        #[arg(long, default_value_t = false)]
+        explain: bool,
+        /// 匹配到多行时，只删除第 n 行候选（从 1 开始）；未指定时交互选择，非交互时报错并列出候选
+        #[arg(long, value_name = "N", conflicts_with = "cidr")]
+        select: Option<usize>,
+        /// 只删除该别名，保留同一行的规范名和其他别名
+        #[arg(long, conflicts_with_all = ["cidr", "select"])]
+        alias: Option<String>,
+        /// 域名不存在时视为成功，不报错
+        #[arg(long, conflicts_with_all = ["cidr", "alias"], default_value_t = false)]
+        if_exists: bool,
+        /// 仅当域名当前的 IP 与之相同时才删除，否则以状态码 5 退出
+        #[arg(long, value_name = "CURRENT_IP", conflicts_with_all = ["cidr", "alias", "tag"])]
+        if_ip_matches: Option<IpAddr>,
+        /// 删除带有该分组标签的所有映射；未指定域名时删除整个分组
+        #[arg(long, value_parser = parse_tag, conflicts_with_all = ["alias", "select", "if_exists"])]
+        tag: Option<String>,
+        /// 只逐行说明将要进行的修改，不写入文件
+        #[arg(long, conflicts_with_all = ["cidr", "alias", "tag"], default_value_t = false)]
+        plan: bool,
     },
     /// 创建新的域名映射
     Create {
         /// 域名
         domain: String,
         /// IP 地址
-        ip: String,
+        #[arg(required_unless_present_any = ["resolve", "stdin_ip", "ipv4", "ipv6"])]
+        ip: Option<String>,
+        /// 双栈映射的 IPv4 地址，可与 --ipv6 同时指定，为同一域名写入两行
+        #[arg(long, conflicts_with_all = ["ip", "resolve", "stdin_ip", "template"])]
+        ipv4: Option<Ipv4Addr>,
+        /// 双栈映射的 IPv6 地址，可与 --ipv4 同时指定，为同一域名写入两行
+        #[arg(long, conflicts_with_all = ["ip", "resolve", "stdin_ip", "template"])]
+        ipv6: Option<Ipv6Addr>,
+        /// 从标准输入读取 IP 地址，如 `echo 10.0.0.5 | hostm create foo.local --stdin-ip`
+        #[arg(long, conflicts_with_all = ["ip", "resolve"], default_value_t = false)]
+        stdin_ip: bool,
+        /// 通过 DNS 解析域名，使用当前解析结果作为 IP
+        #[arg(long, conflicts_with = "ip", default_value_t = false)]
+        resolve: bool,
+        /// 解析时优先使用 IPv4 地址
+        #[arg(long, conflicts_with = "ip", default_value_t = false)]
+        prefer_ipv4: bool,
+        /// 解析时优先使用 IPv6 地址
+        #[arg(long, conflicts_with_all = ["ip", "prefer_ipv4"], default_value_t = false)]
+        prefer_ipv6: bool,
+        /// 同时写入解析到的 IPv4 和 IPv6 地址（两行）
+        #[arg(long, conflicts_with_all = ["ip", "prefer_ipv4", "prefer_ipv6"], default_value_t = false)]
+        both: bool,
+        /// 将域名和 IP 视为模板，替换其中的 {env}、{ip} 变量
+        #[arg(long, default_value_t = false)]
+        template: bool,
+        /// 模板变量 {env} 的值
+        #[arg(long)]
+        env: Option<String>,
+        /// 模板变量 {ip} 的值
+        #[arg(long = "ip", id = "ip_var")]
+        ip_var: Option<String>,
+        /// 映射的有效期，如 30m、2h、7d，到期后可用 prune-expired 清理
+        #[arg(long, value_parser = parse_duration)]
+        expires: Option<TimeDelta>,
+        /// 注释中署名的用户或服务，默认为 $USER
+        #[arg(long)]
+        user: Option<String>,
+        /// 将映射标记为已验证（在注释中追加 hostm:trusted）
+        #[arg(long, default_value_t = false)]
+        trust: bool,
+        /// 分组标签（在注释中追加 hostm:tag=<标签>），便于之后按组列出或删除
+        #[arg(long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// 别名，写在规范名之后的同一行，可重复指定
+        #[arg(long = "alias", value_name = "NAME")]
+        aliases: Vec<String>,
+        /// 域名和别名不以该后缀结尾时自动追加，如 .project.local；未指定时使用环境变量 HOSTM_DOMAIN_SUFFIX
+        #[arg(long, value_name = "SUFFIX")]
+        domain_suffix: Option<String>,
+        /// 域名已存在时视为成功，不修改文件
+        #[arg(long, default_value_t = false)]
+        if_not_exists: bool,
+        /// hosts 文件不存在时先创建，如为项目单独维护的 ./dev.hosts
+        #[arg(long, default_value_t = false)]
+        create_missing_file: bool,
+        /// 插入到 `# BEGIN <名称>` / `# END <名称>` 分区的末尾，分区不存在时在文件末尾新建
+        #[arg(long, value_name = "NAME")]
+        section: Option<String>,
+        /// 插入到该域名所在行之前
+        #[arg(long, value_name = "ANCHOR_DOMAIN", conflicts_with = "section")]
+        before: Option<String>,
+        /// 插入到该域名所在行之后
+        #[arg(long, value_name = "ANCHOR_DOMAIN", conflicts_with_all = ["section", "before"])]
+        after: Option<String>,
+        /// --before/--after 的锚点域名不存在时的处理方式
+        #[arg(long, value_enum, default_value_t = AnchorFallback::Error)]
+        fallback: AnchorFallback,
+    },
+    /// 将映射注释中的 hostm 时间戳刷新为当前时间，不修改 IP、域名和其他注释
+    Touch {
+        /// 域名
+        domain: String,
+        /// 匹配到多行时，操作第 n 行候选（从 1 开始）；未指定时交互选择，非交互时报错并列出候选
+        #[arg(long, value_name = "N")]
+        select: Option<usize>,
+        /// 注释中署名的用户或服务，默认为 $USER
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// 用 #hostm:disabled 前缀注释掉域名映射，使其暂时失效
+    Disable {
+        /// 要停用的域名
+        domain: String,
+        /// 停用原因，记录在注释中
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// 恢复被 disable 注释掉的域名映射
+    Enable {
+        /// 要恢复的域名
+        domain: String,
+    },
+    /// 将 hosts 文件恢复为当前平台的默认内容，原内容总会先备份
+    Reset {
+        /// 确认覆盖，不再交互询问
+        #[arg(long, default_value_t = false)]
+        confirm: bool,
+    },
+    /// 按 JSON 或 YAML 清单生成完整的 hosts 文件并覆盖目标文件，原内容总会先备份
+    Generate {
+        /// 清单文件，描述头部注释、分区、映射及排序规则；扩展名为 .yaml/.yml 时按 YAML 解析，否则按 JSON
+        #[arg(value_hint = ValueHint::FilePath)]
+        manifest: PathBuf,
+        /// 只预览变更，不写入文件
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// 从 BIND 格式的 zone 文件导入 A/AAAA 记录：域名不存在时创建映射，IP 不同时更新；其他类型的记录跳过并给出警告
+    ImportZone {
+        /// zone 文件
+        #[arg(value_hint = ValueHint::FilePath)]
+        zone_file: PathBuf,
+        /// 展开 @ 和相对名称时使用的域，如 internal.；zone 文件中的 $ORIGIN 会覆盖它
+        #[arg(long)]
+        origin: Option<String>,
+        /// 只预览变更，不写入文件
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// 删除所有已过期的映射
+    PruneExpired,
+    /// 清除由 hostm 管理的全部映射（或某个分区内的全部映射），不影响其他行；删除前需确认
+    Clear {
+        /// 删除该名称的 # BEGIN/# END 分区内的全部映射，保留分区标记；未指定时删除注释中带有 created/updated by hostm 的映射
+        #[arg(long, value_name = "NAME")]
+        section: Option<String>,
+        /// 不再交互确认
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+    /// 合并连续的空行、去掉行尾空白和文件末尾的空行
+    Compact {
+        /// 只预览变更，不写入文件
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// 删除所有由 hostm 创建或更新的映射（注释中带有 created/updated by hostm），保留其他行
+    Trim {
+        /// 只处理该名称的 # BEGIN/# END 分区
+        #[arg(long, value_name = "NAME")]
+        section: Option<String>,
+        /// 只预览变更，不写入文件
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// 合并同一域名的重复映射，只保留最后出现的一处（及其注释）
+    Defrag {
+        /// 同一域名指向不同 IP 时，仍以最后出现的映射为准进行合并
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// 处理上次中断的写入：默认按预写日志完成写入
+    Recover {
+        /// 放弃未完成的写入，保留 hosts 文件当前内容
+        #[arg(long, default_value_t = false)]
+        abort: bool,
     },
+    /// 锁定 hosts 文件，锁定期间拒绝所有修改操作
+    Lock {
+        /// 锁定原因，会在修改被拒绝时显示
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// 解除 hostm lock 设置的锁定
+    Unlock,
     /// 查找域名映射
     Search {
         /// 要查找的域名（支持部分匹配）
         domain: String,
+        /// 以 "域名 -> IP (行号)" 的紧凑格式输出匹配结果
+        #[arg(long, default_value_t = false)]
+        summary: bool,
+        /// 最多输出的匹配数量，0 表示不限制
+        #[arg(long, default_value_t = 0)]
+        max_results: usize,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// 只查找 IP 位于该网段内的映射，如 10.0.0.0/8
+        #[arg(long, visible_alias = "scope", value_parser = parse_cidr)]
+        cidr: Option<Cidr>,
+        /// 只输出匹配数量；没有匹配时以非零状态码退出
+        #[arg(long, default_value_t = false)]
+        count_only: bool,
+        /// 在每个匹配前后各显示 N 行上下文（类似 grep -C）
+        #[arg(short = 'C', long, value_name = "N", conflicts_with_all = ["summary", "count_only"])]
+        context: Option<usize>,
+        /// 在每个匹配前显示 N 行上下文，优先于 --context
+        #[arg(short = 'B', long, value_name = "N", conflicts_with_all = ["summary", "count_only"])]
+        before: Option<usize>,
+        /// 在每个匹配后显示 N 行上下文，优先于 --context
+        #[arg(short = 'A', long, value_name = "N", conflicts_with_all = ["summary", "count_only"])]
+        after: Option<usize>,
+    },
+    /// 列出所有域名映射
+    List {
+        /// 跳过前 N 条映射
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        /// 最多输出的映射数量，0 表示不限制
+        #[arg(long, default_value_t = 0)]
+        limit: usize,
+        /// 按最长 IP 对齐各列输出
+        #[arg(long, visible_alias = "pretty", default_value_t = false)]
+        align_columns: bool,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// 只列出 IP 位于该网段内的映射，如 10.0.0.0/8
+        #[arg(long, visible_alias = "scope", value_parser = parse_cidr)]
+        cidr: Option<Cidr>,
+        /// 只列出由该用户或服务创建或更新的映射
+        #[arg(long)]
+        user: Option<String>,
+        /// 只列出标记为已验证的映射
+        #[arg(long, default_value_t = false)]
+        trusted: bool,
+        /// 只列出未标记为已验证的映射
+        #[arg(long, conflicts_with = "trusted", default_value_t = false)]
+        untrusted: bool,
+        /// 只列出带有该分组标签的映射
+        #[arg(long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// 只列出由 hostm 创建或更新的映射（注释中带有 created/updated by hostm）
+        #[arg(long, default_value_t = false)]
+        only_managed: bool,
+        /// 只列出手动添加、不带 hostm 注释的映射
+        #[arg(long, conflicts_with = "only_managed", default_value_t = false)]
+        only_unmanaged: bool,
+        /// 只列出指向 0.0.0.0 或 127.0.0.1 的屏蔽映射（不含 localhost 本身）
+        #[arg(long, default_value_t = false)]
+        only_blocked: bool,
+        /// 只列出 hostm 时间戳不早于该时间的映射，如 2024-01-01 或 "2024-01-01 08:00:00"
+        #[arg(long, value_parser = parse_since)]
+        since: Option<NaiveDateTime>,
+        /// 只列出 hostm 时间戳不晚于该时间的映射；只给日期时包含当天
+        #[arg(long, value_parser = parse_until)]
+        until: Option<NaiveDateTime>,
+        /// 使用 --since/--until 时也列出没有可解析 hostm 时间戳的映射
+        #[arg(long, default_value_t = false)]
+        include_undated: bool,
+        /// 只输出匹配数量；没有匹配时以非零状态码退出
+        #[arg(long, default_value_t = false)]
+        count_only: bool,
+    },
+    /// 显示域名所在的映射；域名不存在时以状态码 1 退出，可用于脚本判断
+    Show {
+        /// 域名，比较时忽略大小写和末尾的点
+        domain: String,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// 导出规范化的映射内容
+    Export {
+        /// 导出格式
+        #[arg(long, value_enum, default_value_t = ExportFormat::Hosts)]
+        format: ExportFormat,
+        /// 保留行内注释
+        #[arg(long, default_value_t = false)]
+        keep_comments: bool,
+    },
+    /// 输出 hosts 文件的 SHA-256，格式兼容 `sha256sum -c`
+    Checksum,
+    /// 检查 hosts 文件的读写权限、备份目录、提权工具等运行环境，不修改任何文件
+    Diagnose,
+    /// 供补全脚本使用：逐行输出以 prefix 开头的已有域名
+    #[command(name = "__complete-domains", hide = true)]
+    CompleteDomains {
+        /// 域名前缀，省略时输出全部域名
+        prefix: Option<String>,
+    },
+    /// 输出 shell 补全脚本，域名参数的补全通过 __complete-domains 读取 hosts 文件
+    Completions {
+        /// 目标 shell
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    /// 按批量文件依次执行 create/update/delete/disable/enable 操作，或应用 unified diff，只写入一次
+    Apply {
+        /// 批量文件（每行一条操作，如 `create <域名> <IP>`、`delete <域名>`、`disable <域名> [原因]`、`enable <域名>`）或 unified diff 文件
+        #[arg(value_hint = ValueHint::FilePath)]
+        batch_file: PathBuf,
+        /// 某条操作失败时的处理方式
+        #[arg(long, value_enum, default_value_t = OnError::Rollback)]
+        on_error: OnError,
+        /// 跳过失败的操作并继续，等同于 --on-error skip；有失败时以非零状态码退出
+        #[arg(long, conflicts_with = "on_error", default_value_t = false)]
+        continue_on_error: bool,
+        /// 任何一条操作失败都不写入文件（默认），等同于 --on-error rollback
+        #[arg(long, conflicts_with_all = ["on_error", "continue_on_error"], default_value_t = false)]
+        fail_fast: bool,
+        /// 只预览变更，不写入文件
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// 管理 `# BEGIN <名称>` / `# END <名称>` 分区
+    Section {
+        #[command(subcommand)]
+        action: SectionCommand,
+    },
+    /// 根据本机运行中的 Docker 容器维护映射（需要使用 --features docker 编译）
+    Docker {
+        #[command(subcommand)]
+        action: DockerCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DockerCommand {
+    /// 为运行中的容器创建或更新 `<IP> <容器名>` 映射，并删除已停止容器的映射；只改动带有 # managed by hostm-docker 的行
+    Sync {
+        /// 只同步连接到该 Docker 网络的容器，使用其在该网络中的 IP；默认使用 bridge 网络
+        #[arg(long, value_name = "NAME")]
+        network: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SectionCommand {
+    /// 输出分区内的所有行（不含 BEGIN/END 标记）
+    Show {
+        /// 分区名称
+        name: String,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = SectionFormat::Text)]
+        format: SectionFormat,
     },
+    /// 重命名分区，只替换 BEGIN/END 标记，不改动分区内容
+    Rename {
+        /// 原分区名称
+        old: String,
+        /// 新分区名称
+        new: String,
+    },
+}
+
+/// section show 的输出格式
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum SectionFormat {
+    /// 原样输出分区内的注释和映射
+    Text,
+    /// 只输出分区内的映射，编码为 JSON 数组
+    Json,
+    /// 只输出分区内的映射，使用标准 hosts 格式
+    Hosts,
+}
+
+/// completions 支持的 shell
+#[derive(Clone, Copy, ValueEnum)]
+enum CompletionShell {
+    /// 使用方法: source <(hostm completions bash)
+    Bash,
+    /// 使用方法: source <(hostm completions zsh)
+    Zsh,
+    /// 使用方法: hostm completions fish | source
+    Fish,
+}
+
+/// create --before/--after 找不到锚点时的处理方式
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum AnchorFallback {
+    /// 报错，不修改文件
+    #[default]
+    Error,
+    /// 追加到文件末尾
+    End,
+}
+
+/// search/list 的输出格式
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// 便于阅读的文本
+    Text,
+    /// 每行一个 JSON 对象，边读边输出
+    Jsonl,
+}
+
+/// export 的输出格式
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ExportFormat {
+    /// 规范化的 hosts 格式：每行一个 IP 和一个域名，IP 使用标准写法，域名转为小写，排序去重
+    Hosts,
+}
+
+/// 批量操作失败时的处理方式
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OnError {
+    /// 停止执行，写入失败前已完成的操作
+    Stop,
+    /// 跳过失败的操作，继续执行后续操作
+    Skip,
+    /// 放弃所有操作，不写入文件
+    Rollback,
+}
+
+impl Commands {
+    /// 是否为不修改 hosts 文件的只读命令
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::Search { .. }
+                | Commands::List { .. }
+                | Commands::Export { .. }
+                | Commands::Show { .. }
+                | Commands::Checksum
+                | Commands::Diagnose
+                | Commands::CompleteDomains { .. }
+                | Commands::Completions { .. }
+                | Commands::Section { action: SectionCommand::Show { .. } }
+        )
+    }
+}
+
+/// 只读命令的结果输出位置：标准输出或文件
+struct Output {
+    writer: Box<dyn Write>,
+}
+
+impl Output {
+    /// 打开输出位置，未指定或为 `-` 时使用标准输出；append 为真时追加到文件末尾
+    fn open(path: Option<&Path>, append: bool) -> Result<Self> {
+        let writer: Box<dyn Write> = match path {
+            Some(path) if path != Path::new("-") => {
+                let file = fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(path)
+                    .with_context(|| format!("无法打开输出文件: {}", path.display()))?;
+                Box::new(std::io::BufWriter::new(file))
+            }
+            _ => Box::new(std::io::stdout()),
+        };
+        Ok(Output { writer })
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 未指定 -f 时依次尝试的 hosts 文件路径
+#[cfg(target_os = "windows")]
+fn hosts_path_candidates() -> Vec<PathBuf> {
+    let root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".to_string());
+    vec![PathBuf::from(root).join(r"System32\drivers\etc\hosts")]
+}
+
+/// 未指定 -f 时依次尝试的 hosts 文件路径；macOS 的 /private/etc/hosts 与 /etc/hosts 是同一文件，
+/// 在 WSL 中还会尝试 Windows 的 hosts 文件
+#[cfg(not(target_os = "windows"))]
+fn hosts_path_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from("/etc/hosts"), PathBuf::from("/private/etc/hosts")];
+    if is_wsl() {
+        candidates.push(PathBuf::from("/mnt/c/Windows/System32/drivers/etc/hosts"));
+    }
+    candidates
+}
+
+/// 是否运行在 WSL 中
+#[cfg(not(target_os = "windows"))]
+fn is_wsl() -> bool {
+    std::env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease").is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+/// 自动查找 hosts 文件：选择第一个存在且可写的候选，都不可写时选择第一个存在的
+fn default_hosts_path(verbose: bool) -> PathBuf {
+    let candidates = hosts_path_candidates();
+    // 以追加方式打开只检查权限，不会修改文件
+    let writable = |path: &&PathBuf| fs::OpenOptions::new().append(true).open(path).is_ok();
+    let chosen = candidates
+        .iter()
+        .find(writable)
+        .or_else(|| candidates.iter().find(|path| path.exists()))
+        .unwrap_or(&candidates[0])
+        .clone();
+    if verbose {
+        let tried: Vec<String> = candidates.iter().map(|path| path.display().to_string()).collect();
+        eprintln!("[verbose] 自动选择 hosts 文件: {}（候选: {}）", chosen.display(), tried.join(", "));
+    }
+    chosen
+}
+
+/// 展开 -f 路径开头的 `~` 并转换为绝对路径，不依赖 shell 的展开；ssh:// 路径原样使用
+fn resolve_hosts_path(path: &Path, verbose: bool) -> Result<PathBuf> {
+    let text = path.to_string_lossy();
+    if text.starts_with("ssh://") {
+        return Ok(path.to_path_buf());
+    }
+    
+    let expanded = match text.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            let home = std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .ok_or_else(|| anyhow::anyhow!("无法展开 {}：未设置 HOME 环境变量", text))?;
+            PathBuf::from(home).join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => path.to_path_buf(),
+    };
+    let absolute = std::path::absolute(&expanded)
+        .with_context(|| format!("无法解析 hosts 文件路径: {}", text))?;
+    if verbose {
+        eprintln!("[verbose] hosts 文件路径: {} -> {}", text, absolute.display());
+    }
+    Ok(absolute)
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    
-    match &args.command {
-        Commands::Update { domain, ip } => {
-            update_domain(domain, ip, &args.hosts_file, args.verbose)
+    // 补全脚本通常在 shell 启动时生成，不依赖 hosts 文件是否存在
+    if let Commands::Completions { shell } = args.command {
+        let mut out = Output::open(args.output.as_deref(), args.append)?;
+        out.write_all(completion_script(shell).as_bytes())?;
+        return out.flush().context("写入输出失败");
+    }
+    let write_options = WriteOptions {
+        normalize_blanks: args.normalize_blanks,
+        align: args.align,
+        trailing_newline: args.trailing_newline,
+        normalize_ipv6: args.normalize_ipv6,
+    };
+    let hosts_files = if args.hosts_file.is_empty() {
+        vec![default_hosts_path(args.verbose)]
+    } else {
+        args.hosts_file
+            .iter()
+            .map(|path| resolve_hosts_path(path, args.verbose))
+            .collect::<Result<_>>()?
+    };
+    let mut stores: Vec<Box<dyn HostsStore>> = hosts_files
+        .iter()
+        .map(|path| {
+            open_store(path, args.check_mtime, write_options, args.expected_checksum.as_deref(), args.max_backups_size, args.verbose)
+        })
+        .collect::<Result<_>>()?;
+    if !args.allow_localhost_edit {
+        stores = stores
+            .into_iter()
+            .map(|inner| -> Box<dyn HostsStore> { Box::new(LocalhostGuard { inner, last_read: RefCell::new(None) }) })
+            .collect();
+    }
+    if args.output_only && args.output_file.is_some() {
+        anyhow::bail!("--output-only 与 --output-file 不能同时使用");
+    }
+    let redirected = args.output_file.is_some() || args.output_only;
+    if redirected {
+        let flag = if args.output_only { "--output-only" } else { "--output-file" };
+        if args.command.is_read_only() {
+            anyhow::bail!("{} 只能用于修改类命令，只读命令请使用 --output", flag);
         }
-        Commands::Delete { domain } => {
-            delete_domain(domain, &args.hosts_file, args.verbose)
+        if matches!(args.command, Commands::Recover { .. } | Commands::Lock { .. } | Commands::Unlock) {
+            anyhow::bail!("{} 不能用于 recover/lock/unlock", flag);
         }
-        Commands::Create { domain, ip } => {
-            create_domain(domain, ip, &args.hosts_file, args.verbose)
+        if args.expected_checksum.is_some() {
+            anyhow::bail!("{} 不会修改 hosts 文件，不能与 --expected-checksum 同时使用", flag);
+        }
+        stores = stores
+            .into_iter()
+            .map(|source| -> Box<dyn HostsStore> {
+                Box::new(RedirectStore {
+                    source,
+                    output: args.output_file.clone(),
+                    write_options,
+                    verbose: args.verbose,
+                })
+            })
+            .collect();
+    }
+    // 写入 --output-file/--output-only 时 hosts 文件不变，不记录审计日志
+    let audit_log = args
+        .audit_log
+        .clone()
+        .or_else(|| std::env::var_os("HOSTM_AUDIT_LOG").filter(|path| !path.is_empty()).map(PathBuf::from));
+    if let Some(path) = audit_log.filter(|_| !redirected && !args.command.is_read_only()) {
+        stores = stores
+            .into_iter()
+            .map(|inner| -> Box<dyn HostsStore> {
+                Box::new(AuditLog { inner, path: path.clone(), last_read: RefCell::new(None) })
+            })
+            .collect();
+    }
+    let sources: Vec<&dyn HostsStore> = stores.iter().map(|s| s.as_ref()).collect();
+    if args.output.is_some() && !args.command.is_read_only() {
+        anyhow::bail!("--output 只能用于只读命令（search/list/export/checksum）");
+    }
+    if args.expected_checksum.is_some() && args.command.is_read_only() {
+        anyhow::bail!("--expected-checksum 只能用于修改类命令");
+    }
+    
+    // 存在未完成的写入时，修改前需先决定完成还是放弃；写入 --output-file/--output-only 时不影响原文件，只给出提示
+    if !matches!(args.command, Commands::Recover { .. }) {
+        if let Some(store) = sources.iter().find(|store| store.has_pending_write()) {
+            if args.command.is_read_only() || redirected {
+                eprintln!("⚠️  {} 存在未完成的写入，可运行 hostm recover 完成或 hostm recover --abort 放弃", store.name());
+            } else {
+                anyhow::bail!(
+                    "{} 存在未完成的写入，请先运行 hostm recover 完成或 hostm recover --abort 放弃",
+                    store.name()
+                );
+            }
         }
-        Commands::Search { domain } => {
-            search_domain(domain, &args.hosts_file, args.verbose)
+    }
+    
+    // 锁定期间只允许只读命令、写入 --output-file/--output-only 的命令以及锁定相关的命令
+    if !args.command.is_read_only()
+        && !redirected
+        && !matches!(args.command, Commands::Lock { .. } | Commands::Unlock | Commands::Recover { .. })
+    {
+        for store in &sources {
+            if let Some(lock) = store.lock_info()? {
+                anyhow::bail!("{} 已锁定（{}），请使用 hostm unlock 解锁后再操作", store.name(), lock);
+            }
         }
     }
-}
 
-/// 更新已存在的域名映射
-fn update_domain(domain: &str, ip: &str, hosts_file: &str, verbose: bool) -> Result<()> {
-    let hosts_path = Path::new(hosts_file);
+    // 防止 -f 写错路径时修改了无关的文件（如 .bashrc）
+    if !args.command.is_read_only()
+        && !args.force
+        && !matches!(args.command, Commands::Lock { .. } | Commands::Unlock | Commands::Recover { .. })
+    {
+        for store in &sources {
+            if store.check().is_ok() && !looks_like_hosts_file(&store.read()?) {
+                eprintln!("⚠️  {} 中大部分内容不是 IP 映射，看起来不像 hosts 文件", store.name());
+                anyhow::bail!("已拒绝修改 {}，确认路径无误后可加上 --force 继续", store.name());
+            }
+        }
+    }
     
-    // 检查文件
-    check_hosts_file(hosts_path)?;
+    let color_choice = if args.no_color { ColorChoice::Never } else { args.color };
+    // 预览和提示输出到标准错误，list 的结果输出到标准输出
+    let color = color_choice.enabled(&std::io::stderr());
+    let comments = |user: Option<&String>| Comments {
+        policy: args.comment_format.clone().map_or(CommentPolicy::Default, CommentPolicy::Custom),
+        user: user.cloned().unwrap_or_else(default_user),
+        tag: None,
+    };
+    let mut out = Output::open(args.output.as_deref(), args.append)?;
     
-    // 读取文件内容
-    let content = fs::read_to_string(hosts_path)
-        .with_context(|| format!("无法读取文件: {}", hosts_file))?;
+    // --count-only 没有匹配时以非零状态码退出
+    let mut no_matches = false;
+    let result = match &args.command {
+        Commands::Update { domain, comment_only: true, comment, explain, select, if_ip_matches, plan, .. } => {
+            let domain = &ascii_domain(domain)?;
+            let comment = comment.as_deref().ok_or_else(|| anyhow::anyhow!("--comment-only 需要同时指定 --comment"))?;
+            let backup = args.backup.contains(&Operation::Update);
+            let matching = MatchOptions {
+                explain: *explain,
+                select: *select,
+                missing_ok: false,
+                expected_ip: *if_ip_matches,
+                plan: *plan,
+            };
+            update_comment_only(domain, comment, single_store(&sources)?, &matching, backup, args.verbose)
+        }
+        Commands::Update { domain, ip, stdin_ip, resolve, prefer_ipv4, prefer_ipv6, explain, select, user, if_ip_matches, tag, plan, .. } => {
+            let domain = &ascii_domain(domain)?;
+            let ip = if *stdin_ip { Some(read_stdin_ip(args.verbose)?) } else { ip.clone() };
+            let family = IpFamily::from_flags(*prefer_ipv4, *prefer_ipv6, false);
+            let ips = resolve_ip_arg(domain, ip.as_deref(), *resolve, family, args.timeout, args.verbose)?;
+            let backup = args.backup.contains(&Operation::Update);
+            let matching = MatchOptions {
+                explain: *explain,
+                select: *select,
+                missing_ok: false,
+                expected_ip: *if_ip_matches,
+                plan: *plan,
+            };
+            let comments = Comments { tag: tag.clone(), ..comments(user.as_ref()) };
+            update_domain(domain, &ips[0], single_store(&sources)?, &comments, &matching, backup, args.verbose)
+        }
+        Commands::Delete { domain, cidr, explain, select, alias, if_exists, if_ip_matches, tag, plan } => {
+            let backup = args.backup.contains(&Operation::Delete);
+            let domain = domain.as_deref().map(ascii_domain).transpose()?;
+            let alias = alias.as_deref().map(ascii_domain).transpose()?;
+            if let Some(alias) = &alias {
+                delete_alias(alias, domain.as_deref(), single_store(&sources)?, backup, args.verbose)
+            } else if cidr.is_some() || tag.is_some() {
+                let scope = DeleteScope { cidr: cidr.as_ref(), tag: tag.as_deref() };
+                delete_in_scope(domain.as_deref(), &scope, single_store(&sources)?, backup, args.verbose)
+            } else {
+                match &domain {
+                    Some(domain) => {
+                        let matching = MatchOptions {
+                            explain: *explain,
+                            select: *select,
+                            missing_ok: *if_exists,
+                            expected_ip: *if_ip_matches,
+                            plan: *plan,
+                        };
+                        delete_domain(domain, single_store(&sources)?, &matching, backup, args.verbose)
+                    }
+                    None => anyhow::bail!("缺少要删除的域名"),
+                }
+            }
+        }
+        Commands::Create { domain, ip, ipv4, ipv6, stdin_ip, resolve, prefer_ipv4, prefer_ipv6, both, template, env, ip_var, expires, user, trust, tag, aliases, domain_suffix, if_not_exists, create_missing_file, section, before, after, fallback } => {
+            let ip = if *stdin_ip { Some(read_stdin_ip(args.verbose)?) } else { ip.clone() };
+            let (domain, ip) = if *template {
+                let mut vars = HashMap::new();
+                if let Some(env) = env {
+                    vars.insert("env".to_string(), env.clone());
+                }
+                if let Some(ip_var) = ip_var {
+                    vars.insert("ip".to_string(), ip_var.clone());
+                }
+                let ip = ip.as_deref().map(|ip| render_template(ip, &vars)).transpose()?;
+                (render_template(domain, &vars)?, ip)
+            } else {
+                if env.is_some() || ip_var.is_some() {
+                    anyhow::bail!("--env 和 --ip 只能与 --template 一起使用");
+                }
+                (domain.clone(), ip)
+            };
+            let suffix = domain_suffix
+                .clone()
+                .or_else(|| std::env::var("HOSTM_DOMAIN_SUFFIX").ok().filter(|suffix| !suffix.is_empty()));
+            let domain = ascii_domain(&with_domain_suffix(&domain, suffix.as_deref()))?.into_owned();
+            let aliases = aliases
+                .iter()
+                .map(|alias| ascii_domain(&with_domain_suffix(alias, suffix.as_deref())).map(Cow::into_owned))
+                .collect::<Result<Vec<_>>>()?;
+            
+            let family = IpFamily::from_flags(*prefer_ipv4, *prefer_ipv6, *both);
+            let ips = if ipv4.is_some() || ipv6.is_some() {
+                let v4 = ipv4.map(|ip| ip.to_string());
+                let v6 = ipv6.map(|ip| ip.to_string());
+                v4.into_iter().chain(v6).collect()
+            } else {
+                resolve_ip_arg(&domain, ip.as_deref(), *resolve, family, args.timeout, args.verbose)?
+            };
+            let backup = args.backup.contains(&Operation::Create);
+            let options = CreateOptions {
+                expires: expires.map(|duration| Utc::now() + duration),
+                trusted: *trust,
+                aliases,
+                exists_ok: *if_not_exists,
+                section: section.clone(),
+                anchor: match (before, after) {
+                    (Some(domain), _) => Some(Anchor { domain: ascii_domain(domain)?.into_owned(), after: false }),
+                    (_, Some(domain)) => Some(Anchor { domain: ascii_domain(domain)?.into_owned(), after: true }),
+                    (None, None) => None,
+                },
+                anchor_fallback: *fallback,
+            };
+            for ip in &ips {
+                // 允许 fe80::1%eth0 形式的区域标识
+                let address = ip.split_once('%').map_or(ip.as_str(), |(address, _)| address);
+                if address.parse::<IpAddr>().is_err() {
+                    anyhow::bail!("无效的 IP 地址: {}", ip);
+                }
+            }
+            // 参数全部校验通过后才创建文件；结果不写回 hosts 文件时不创建
+            if *create_missing_file && !redirected {
+                for path in &hosts_files {
+                    create_missing_hosts_file(path, args.verbose)?;
+                }
+            }
+            let comments = Comments { tag: tag.clone(), ..comments(user.as_ref()) };
+            create_domain(&domain, &ips, &options, single_store(&sources)?, &comments, backup, args.verbose)
+        }
+        Commands::Touch { domain, select, user } => {
+            let domain = &ascii_domain(domain)?;
+            let backup = args.backup.contains(&Operation::Update);
+            touch_domain(domain, *select, single_store(&sources)?, &comments(user.as_ref()), backup, args.verbose)
+        }
+        Commands::Disable { domain, reason } => {
+            let backup = args.backup.contains(&Operation::Update);
+            toggle_domain(domain, false, reason.as_deref(), single_store(&sources)?, backup, args.verbose)
+        }
+        Commands::Enable { domain } => {
+            let backup = args.backup.contains(&Operation::Update);
+            toggle_domain(domain, true, None, single_store(&sources)?, backup, args.verbose)
+        }
+        Commands::PruneExpired => {
+            let backup = args.backup.contains(&Operation::Delete);
+            prune_expired(single_store(&sources)?, backup, args.verbose)
+        }
+        Commands::Reset { confirm } => reset_hosts(single_store(&sources)?, *confirm, args.verbose),
+        Commands::Generate { manifest, dry_run } => {
+            generate_hosts(manifest, single_store(&sources)?, *dry_run, color, args.verbose)
+        }
+        Commands::ImportZone { zone_file, origin, dry_run } => {
+            let options = ImportOptions { origin: origin.as_deref(), dry_run: *dry_run, color };
+            import_zone(zone_file, &options, single_store(&sources)?, &args.backup, &comments(None), args.verbose)
+        }
+        Commands::Clear { section, yes } => {
+            let backup = args.backup.contains(&Operation::Delete);
+            clear_hosts(single_store(&sources)?, section.as_deref(), *yes, backup, args.verbose)
+        }
+        Commands::Trim { section, dry_run } => {
+            let backup = args.backup.contains(&Operation::Delete);
+            trim_hosts(single_store(&sources)?, section.as_deref(), *dry_run, backup, color, args.verbose)
+        }
+        Commands::Compact { dry_run } => {
+            let backup = args.backup.contains(&Operation::Update);
+            compact_hosts(single_store(&sources)?, *dry_run, backup, color, args.verbose)
+        }
+        Commands::Defrag { force } => {
+            let backup = args.backup.contains(&Operation::Update);
+            defrag_domains(single_store(&sources)?, *force, backup, args.verbose)
+        }
+        Commands::Recover { abort } => {
+            let store = single_store(&sources)?;
+            if !store.recover(!*abort)? {
+                eprintln!("✅ 没有未完成的写入");
+            } else if *abort {
+                eprintln!("✅ 已放弃未完成的写入: {}", store.name());
+            } else {
+                eprintln!("✅ 已完成中断的写入: {}", store.name());
+            }
+            Ok(())
+        }
+        Commands::Lock { reason } => {
+            let store = single_store(&sources)?;
+            if let Some(lock) = store.lock_info()? {
+                anyhow::bail!("{} 已锁定（{}）", store.name(), lock);
+            }
+            store.set_lock(Some(&LockInfo {
+                locked_at: Local::now(),
+                user: default_user(),
+                reason: reason.clone(),
+            }))?;
+            eprintln!("🔒 已锁定: {}", store.name());
+            Ok(())
+        }
+        Commands::Unlock => {
+            let store = single_store(&sources)?;
+            if store.lock_info()?.is_none() {
+                eprintln!("✅ 没有锁定: {}", store.name());
+            } else {
+                store.set_lock(None)?;
+                eprintln!("🔓 已解除锁定: {}", store.name());
+            }
+            Ok(())
+        }
+        Commands::Search { domain, summary, max_results, format, cidr, count_only, context, before, after } => {
+            if *format == OutputFormat::Jsonl && (context.is_some() || before.is_some() || after.is_some()) {
+                anyhow::bail!("--context/--before/--after 只能用于文本格式");
+            }
+            let options = SearchOptions {
+                summary: *summary,
+                max_results: *max_results,
+                format: *format,
+                scope: *cidr,
+                count_only: *count_only,
+                before: before.or(*context).unwrap_or(0),
+                after: after.or(*context).unwrap_or(0),
+            };
+            search_domain(domain, &sources, &options, &mut out, args.verbose)
+                .map(|count| no_matches = *count_only && count == 0)
+        }
+        Commands::List { offset, limit, align_columns, format, cidr, user, trusted, untrusted, tag, only_managed, only_unmanaged, only_blocked, since, until, include_undated, count_only } => {
+            let options = ListOptions {
+                offset: *offset,
+                limit: *limit,
+                align_columns: *align_columns,
+                format: *format,
+                scope: *cidr,
+                user: user.as_deref(),
+                trusted: if *trusted { Some(true) } else if *untrusted { Some(false) } else { None },
+                tag: tag.as_deref(),
+                managed: if *only_managed { Some(true) } else if *only_unmanaged { Some(false) } else { None },
+                blocked: *only_blocked,
+                since: *since,
+                until: *until,
+                include_undated: *include_undated,
+                count_only: *count_only,
+                // 输出到文件时不写入颜色控制符
+                color: color_choice.enabled(&std::io::stdout())
+                    && args.output.as_deref().is_none_or(|path| path == Path::new("-")),
+            };
+            list_domains(&sources, &options, &mut out, args.verbose)
+                .map(|count| no_matches = *count_only && count == 0)
+        }
+        Commands::Show { domain, format } => {
+            show_domain(&sources, domain, *format, &mut out).map(|found| no_matches = !found)
+        }
+        Commands::Export { format, keep_comments } => {
+            export_hosts(&sources, *format, *keep_comments, &mut out, args.verbose)
+        }
+        Commands::Checksum => print_checksums(&sources, &mut out),
+        Commands::Diagnose => diagnose(&hosts_files, &mut out),
+        Commands::Section { action: SectionCommand::Show { name, format } } => {
+            show_section(&sources, name, *format, &mut out, args.verbose)
+        }
+        Commands::Section { action: SectionCommand::Rename { old, new } } => {
+            let backup = args.backup.contains(&Operation::Update);
+            rename_section(old, new, single_store(&sources)?, backup, args.verbose)
+        }
+        Commands::Docker { action: DockerCommand::Sync { network } } => {
+            let backup = args.backup.contains(&Operation::Update);
+            docker_sync(network.as_deref(), single_store(&sources)?, backup, args.verbose)
+        }
+        Commands::CompleteDomains { prefix } => complete_domains(&sources, prefix.as_deref().unwrap_or(""), &mut out),
+        Commands::Completions { .. } => unreachable!("补全脚本已在打开 hosts 文件前输出"),
+        Commands::Apply { batch_file, on_error, continue_on_error, fail_fast: _, dry_run } => {
+            let on_error = if *continue_on_error { OnError::Skip } else { *on_error };
+            let options = ApplyOptions { on_error, dry_run: *dry_run, color };
+            apply_batch(batch_file, single_store(&sources)?, &options, &args.backup, &comments(None), args.verbose)
+        }
+    };
     
-    if verbose {
-        println!("[verbose] 更新域名映射: {} -> {}", domain, ip);
+    out.flush().context("写入输出失败")?;
+    if no_matches {
+        std::process::exit(1);
+    }
+    
+    // 对可建议替代命令的错误，额外输出可直接复制的命令
+    if let Err(e) = &result {
+        // --if-ip-matches 条件不满足时使用单独的状态码，便于脚本区分
+        if let Some(err) = e.downcast_ref::<IpMismatch>() {
+            eprintln!("Error: {}", err);
+            std::process::exit(5);
+        }
+        if let Some(err) = e.downcast_ref::<HostsError>() {
+            let hosts_args: String = if args.hosts_file.is_empty() {
+                String::new()
+            } else {
+                args.hosts_file.iter().map(|p| format!(" -f {}", p.display())).collect()
+            };
+            let suggestion = err.suggestion(&hosts_args);
+            eprintln!("Error: {}", err);
+            if color {
+                eprintln!("💡 可以运行: \x1b[1;32m{}\x1b[0m", suggestion);
+            } else {
+                eprintln!("💡 可以运行: {}", suggestion);
+            }
+            std::process::exit(1);
+        }
     }
     
-    let new_content = update_existing_domain(&content, domain, ip, verbose)?;
+    result
+}
+
+/// 修改类命令只能作用于单个 hosts 文件
+fn single_store<'a>(stores: &[&'a dyn HostsStore]) -> Result<&'a dyn HostsStore> {
+    match stores {
+        [store] => Ok(*store),
+        _ => anyhow::bail!("修改类命令只能指定一个 hosts 文件，当前指定了 {} 个", stores.len()),
+    }
+}
+
+/// 替换模板中的 {name} 变量，遇到未定义的变量时报错
+fn render_template(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = template;
     
-    // 写入文件
-    write_hosts_file(hosts_path, &new_content, hosts_file, verbose)?;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("模板缺少右花括号: {}", template))?;
+        let name = &after[..end];
+        let value = vars
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("模板变量 '{{{}}}' 未定义: {}", name, template))?;
+        result.push_str(value);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
     
-    println!("✅ 已更新域名映射: {} -> {}", domain, ip);
-    Ok(())
+    Ok(result)
 }
 
-/// 删除域名映射
-fn delete_domain(domain: &str, hosts_file: &str, verbose: bool) -> Result<()> {
-    let hosts_path = Path::new(hosts_file);
+/// 自动生成的行尾注释格式
+enum CommentPolicy {
+    /// `# <action> by <user> via hostm <日期时间>`，没有用户名时为 `# <action> by hostm <日期时间>`
+    Default,
+    /// 用户提供的模板，可使用 {action}、{date}、{datetime}、{user} 变量
+    Custom(String),
+}
+
+impl CommentPolicy {
+    /// 生成带 `# ` 前缀的注释，action 为 created、updated 等操作名
+    fn render(&self, action: &str, user: &str) -> Result<String> {
+        let now = Local::now();
+        match self {
+            CommentPolicy::Default if user.is_empty() => {
+                Ok(format!("# {} by hostm {}", action, now.format("%Y-%m-%d %H:%M:%S")))
+            }
+            CommentPolicy::Default => {
+                Ok(format!("# {} by {} via hostm {}", action, user, now.format("%Y-%m-%d %H:%M:%S")))
+            }
+            CommentPolicy::Custom(template) => {
+                let vars = HashMap::from([
+                    ("action".to_string(), action.to_string()),
+                    ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+                    ("datetime".to_string(), now.format("%Y-%m-%d %H:%M:%S").to_string()),
+                    ("user".to_string(), user.to_string()),
+                ]);
+                Ok(format!("# {}", render_template(template, &vars)?))
+            }
+        }
+    }
+}
+
+/// 生成注释所需的格式与署名
+struct Comments {
+    policy: CommentPolicy,
+    /// 署名的用户或服务，为空时不署名
+    user: String,
+    /// 写入注释的分组标签
+    tag: Option<String>,
+}
+
+impl Comments {
+    fn render(&self, action: &str) -> Result<String> {
+        self.policy.render(action, &self.user)
+    }
+}
+
+/// 未指定 --user 时的署名：当前登录用户
+fn default_user() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default()
+}
+
+/// 解析域名时选择的地址族
+#[derive(Clone, Copy, PartialEq)]
+enum IpFamily {
+    /// 使用系统解析器返回的第一个地址
+    System,
+    V4,
+    V6,
+    /// IPv4 和 IPv6 各取一个
+    Both,
+}
+
+impl IpFamily {
+    fn from_flags(prefer_ipv4: bool, prefer_ipv6: bool, both: bool) -> Self {
+        if both {
+            IpFamily::Both
+        } else if prefer_ipv4 {
+            IpFamily::V4
+        } else if prefer_ipv6 {
+            IpFamily::V6
+        } else {
+            IpFamily::System
+        }
+    }
+}
+
+/// 从标准输入读取 IP 地址（--stdin-ip），去掉首尾空白后校验格式
+fn read_stdin_ip(verbose: bool) -> Result<String> {
+    if std::io::stdin().is_terminal() {
+        anyhow::bail!("--stdin-ip 需要通过管道提供 IP 地址，如 echo 10.0.0.5 | hostm create foo.local --stdin-ip");
+    }
     
-    // 检查文件
-    check_hosts_file(hosts_path)?;
+    let mut input = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).context("读取标准输入失败")?;
+    let ip = input.trim();
+    if ip.is_empty() {
+        anyhow::bail!("标准输入中没有 IP 地址");
+    }
+    ip.parse::<IpAddr>()
+        .map_err(|_| anyhow::anyhow!("标准输入中的 '{}' 不是有效的 IP 地址", ip))?;
     
-    // 读取文件内容
-    let content = fs::read_to_string(hosts_path)
-        .with_context(|| format!("无法读取文件: {}", hosts_file))?;
+    if verbose {
+        eprintln!("[verbose] 从标准输入读取到 IP: {}", ip);
+    }
+    Ok(ip.to_string())
+}
+
+/// 确定要写入的 IP：使用命令行参数，或在 --resolve 时查询 DNS
+fn resolve_ip_arg(domain: &str, ip: Option<&str>, resolve: bool, family: IpFamily, timeout_ms: u64, verbose: bool) -> Result<Vec<String>> {
+    if !resolve {
+        return ip
+            .map(|s| vec![s.to_string()])
+            .ok_or_else(|| anyhow::anyhow!("缺少 IP 地址参数"));
+    }
     
     if verbose {
-        println!("[verbose] 删除域名: {}", domain);
+        eprintln!("[verbose] 解析域名: {}", domain);
+    }
+    
+    // 系统解析器没有超时参数，放到单独的线程里查询，超时后不再等待
+    let (tx, rx) = mpsc::channel();
+    let host = domain.to_string();
+    thread::spawn(move || {
+        let _ = tx.send((host.as_str(), 0).to_socket_addrs().map(|addrs| addrs.collect::<Vec<_>>()));
+    });
+    let addrs: Vec<IpAddr> = match rx.recv_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(result) => result.with_context(|| format!("无法解析域名: {}", domain))?,
+        Err(_) => anyhow::bail!("TIMEOUT: 解析域名 '{}' 超过 {} 毫秒", domain, timeout_ms),
     }
+    .into_iter()
+    .map(|addr| addr.ip())
+    .collect();
+    let v4 = addrs.iter().find(|ip| ip.is_ipv4());
+    let v6 = addrs.iter().find(|ip| ip.is_ipv6());
     
-    let new_content = remove_domain(&content, domain, verbose)?;
+    // 优先的地址族不存在时回退到另一个
+    let picked: Vec<&IpAddr> = match family {
+        IpFamily::System => addrs.first().into_iter().collect(),
+        IpFamily::V4 => v4.or(v6).into_iter().collect(),
+        IpFamily::V6 => v6.or(v4).into_iter().collect(),
+        IpFamily::Both => v4.into_iter().chain(v6).collect(),
+    };
     
-    // 写入文件
-    write_hosts_file(hosts_path, &new_content, hosts_file, verbose)?;
+    if picked.is_empty() {
+        anyhow::bail!("域名 '{}' 没有解析到任何地址", domain);
+    }
     
-    println!("✅ 已删除域名映射: {}", domain);
-    Ok(())
+    let ips: Vec<String> = picked.iter().map(|ip| ip.to_string()).collect();
+    if verbose {
+        eprintln!("[verbose] 解析结果: {} -> {}", domain, ips.join(", "));
+    }
+    
+    Ok(ips)
 }
 
-/// 创建新的域名映射
-fn create_domain(domain: &str, ip: &str, hosts_file: &str, verbose: bool) -> Result<()> {
-    let hosts_path = Path::new(hosts_file);
-    
+/// 更新已存在的域名映射
+fn update_domain(
+    domain: &str,
+    ip: &str,
+    store: &dyn HostsStore,
+    comments: &Comments,
+    matching: &MatchOptions,
+    backup: bool,
+    verbose: bool,
+) -> Result<()> {
     // 检查文件
-    check_hosts_file(hosts_path)?;
+    store.check()?;
     
     // 读取文件内容
-    let content = fs::read_to_string(hosts_path)
-        .with_context(|| format!("无法读取文件: {}", hosts_file))?;
+    let content = store.read()?;
     
     if verbose {
-        println!("[verbose] 创建域名映射: {} -> {}", domain, ip);
+        eprintln!("[verbose] 更新域名映射: {} -> {}", domain, ip);
+    }
+    
+    // 双栈映射按新 IP 的地址族选择要更新的行
+    let target = match (matching.select, dual_stack_lines(&content, domain)?) {
+        (None, Some((v4, v6))) => Some(if ip.parse::<IpAddr>().is_ok_and(|ip| ip.is_ipv6()) { v6 } else { v4 }),
+        _ => select_match(&content, domain, matching.select)?,
+    };
+    if let Some(expected) = matching.expected_ip {
+        check_current_ip(&content, domain, target, expected, false)?;
+    }
+    let new_content = update_existing_domain(&content, domain, ip, comments, target, verbose).inspect_err(|_| {
+        if matching.explain {
+            explain_no_match(&content, domain);
+        }
+        suggest_similar_domain(&content, domain);
+    })?;
+    
+    if matching.plan {
+        ChangeReport::between(&content, &new_content).print();
+        return Ok(());
     }
     
-    let new_content = add_new_domain(&content, domain, ip, verbose)?;
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
     
     // 写入文件
-    write_hosts_file(hosts_path, &new_content, hosts_file, verbose)?;
+    store.write(&new_content)?;
     
-    println!("✅ 已创建域名映射: {} -> {}", domain, ip);
+    let previous = ChangeReport::between(&content, &new_content).previous_ips();
+    if previous.is_empty() {
+        eprintln!("✅ 已更新域名映射: {} -> {}", domain, ip);
+    } else {
+        eprintln!("✅ 已更新域名映射: {}: {} -> {}", domain, previous.join(", "), ip);
+    }
     Ok(())
 }
 
-/// 查找域名映射
-fn search_domain(domain: &str, hosts_file: &str, verbose: bool) -> Result<()> {
-    let hosts_path = Path::new(hosts_file);
-    
+/// 只替换域名映射的注释，保留 IP 和域名
+fn update_comment_only(
+    domain: &str,
+    comment: &str,
+    store: &dyn HostsStore,
+    matching: &MatchOptions,
+    backup: bool,
+    verbose: bool,
+) -> Result<()> {
     // 检查文件
-    check_hosts_file(hosts_path)?;
+    store.check()?;
     
     // 读取文件内容
-    let content = fs::read_to_string(hosts_path)
-        .with_context(|| format!("无法读取文件: {}", hosts_file))?;
+    let content = store.read()?;
     
     if verbose {
-        println!("[verbose] 查找包含 '{}' 的行", domain);
+        eprintln!("[verbose] 更新域名注释: {} -> {}", domain, comment);
     }
     
-    let mut found = false;
-    for (line_num, line) in content.lines().enumerate() {
-        if line.contains(domain) {
-            if !found {
-                println!("🔍 找到包含 '{}' 的行:", domain);
-                found = true;
-            }
-            println!("  {}: {}", line_num + 1, line);
+    let target = select_match(&content, domain, matching.select)?;
+    if let Some(expected) = matching.expected_ip {
+        check_current_ip(&content, domain, target, expected, false)?;
+    }
+    let new_content = replace_comment(&content, domain, comment, target, verbose).inspect_err(|_| {
+        if matching.explain {
+            explain_no_match(&content, domain);
         }
+        suggest_similar_domain(&content, domain);
+    })?;
+    
+    if matching.plan {
+        ChangeReport::between(&content, &new_content).print();
+        return Ok(());
     }
     
-    if !found {
-        println!("❌ 未找到包含 '{}' 的行", domain);
+    // 写入前备份
+    if backup {
+        store.backup()?;
     }
     
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已更新域名注释: {} # {}", domain, comment);
     Ok(())
 }
 
-/// 检查 hosts 文件
-fn check_hosts_file(hosts_path: &Path) -> Result<()> {
-    if !hosts_path.exists() {
-        anyhow::bail!("hosts 文件不存在: {}", hosts_path.display());
+/// 刷新映射注释中的 hostm 时间戳
+fn touch_domain(
+    domain: &str,
+    select: Option<usize>,
+    store: &dyn HostsStore,
+    comments: &Comments,
+    backup: bool,
+    verbose: bool,
+) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    if verbose {
+        eprintln!("[verbose] 刷新域名时间戳: {}", domain);
     }
     
-    if !hosts_path.is_file() {
-        anyhow::bail!("路径不是文件: {}", hosts_path.display());
+    let target = select_match(&content, domain, select)?;
+    let new_content = refresh_timestamp(&content, domain, comments, target, verbose).inspect_err(|_| {
+        suggest_similar_domain(&content, domain);
+    })?;
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
     }
     
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已刷新域名映射的时间戳: {}", domain);
+    Ok(())
+}
+
+/// update/delete 匹配行及执行方式的选项
+struct MatchOptions {
+    /// 未找到域名时解释原因
+    explain: bool,
+    /// 匹配到多行时要操作的候选序号（从 1 开始）
+    select: Option<usize>,
+    /// 域名不存在时视为成功
+    missing_ok: bool,
+    /// 要求被操作的行当前指向该 IP（--if-ip-matches）
+    expected_ip: Option<IpAddr>,
+    /// 只输出变更计划，不写入文件
+    plan: bool,
+}
+
+/// --if-ip-matches 指定的 IP 与域名当前的 IP 不一致
+#[derive(Debug)]
+struct IpMismatch {
+    domain: String,
+    expected: IpAddr,
+    actual: String,
+}
+
+impl fmt::Display for IpMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "域名 '{}' 当前的 IP 为 {}，与 --if-ip-matches 指定的 {} 不一致，未做修改",
+            self.domain, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for IpMismatch {}
+
+/// 检查将被操作的行是否都指向 expected；all 为假时只检查第一处匹配，与 update 的行为一致
+fn check_current_ip(content: &str, domain: &str, target: Option<usize>, expected: IpAddr, all: bool) -> Result<()> {
+    let domain_regex = domain_regex(domain)?;
+    let affected = content
+        .lines()
+        .enumerate()
+        .filter(|(line_idx, line)| target.is_none_or(|target| target == *line_idx) && domain_regex.is_match(line))
+        .filter_map(|(_, line)| parse_mapping(line))
+        .take(if all { usize::MAX } else { 1 });
+    
+    for (ip, _) in affected {
+        if ip.parse::<IpAddr>().ok() != Some(expected) {
+            return Err(IpMismatch {
+                domain: domain.to_string(),
+                expected,
+                actual: ip.to_string(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// 域名恰好有一条 IPv4 和一条 IPv6 映射（双栈）时，返回两行的下标 (IPv4, IPv6)
+fn dual_stack_lines(content: &str, domain: &str) -> Result<Option<(usize, usize)>> {
+    let domain_regex = domain_regex(domain)?;
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        if !domain_regex.is_match(line) {
+            continue;
+        }
+        match parse_mapping(line).map(|(ip, _)| ip.contains(':')) {
+            Some(true) => v6.push(line_idx),
+            Some(false) => v4.push(line_idx),
+            None => {}
+        }
+    }
+    Ok(match (v4.as_slice(), v6.as_slice()) {
+        ([v4], [v6]) => Some((*v4, *v6)),
+        _ => None,
+    })
+}
+
+/// 匹配到多行时确定要操作的行（content 中的行下标）；只有一处匹配时返回 None，按默认方式处理
+fn select_match(content: &str, domain: &str, select: Option<usize>) -> Result<Option<usize>> {
+    let domain_regex = domain_regex(domain)?;
+    let candidates: Vec<(usize, &str)> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| ip_regex().is_match(line) && domain_regex.is_match(line))
+        .collect();
+    
+    // 没有匹配时交给后续流程报告未找到
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    if let Some(n) = select {
+        return match candidates.get(n.wrapping_sub(1)) {
+            Some((line_idx, _)) => Ok(Some(*line_idx)),
+            None => anyhow::bail!("--select {} 超出范围，共有 {} 个候选", n, candidates.len()),
+        };
+    }
+    if candidates.len() == 1 {
+        return Ok(None);
+    }
+    
+    let listing: Vec<String> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, (line_idx, line))| format!("  [{}] line {}: {}", i + 1, line_idx + 1, line))
+        .collect();
+    
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "域名 '{}' 匹配到 {} 行，请使用 --select <n> 指定要操作的行:\n{}",
+            domain,
+            candidates.len(),
+            listing.join("\n")
+        );
+    }
+    
+    eprintln!("🔍 域名 '{}' 匹配到 {} 行:", domain, candidates.len());
+    for entry in &listing {
+        eprintln!("{}", entry);
+    }
+    eprint!("请选择要操作的行 [1-{}]: ", candidates.len());
+    std::io::stderr().flush()?;
+    
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).context("读取输入失败")?;
+    let n: usize = answer.trim().parse().with_context(|| format!("无效的序号: {}", answer.trim()))?;
+    match candidates.get(n.wrapping_sub(1)) {
+        Some((line_idx, _)) => Ok(Some(*line_idx)),
+        None => anyhow::bail!("序号 {} 超出范围，共有 {} 个候选", n, candidates.len()),
+    }
+}
+
+/// 删除域名映射
+fn delete_domain(domain: &str, store: &dyn HostsStore, matching: &MatchOptions, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    if verbose {
+        eprintln!("[verbose] 删除域名: {}", domain);
+    }
+    
+    // 双栈映射的 IPv4 和 IPv6 两行一起删除
+    let target = match (matching.select, dual_stack_lines(&content, domain)?) {
+        (None, Some(_)) => None,
+        _ => select_match(&content, domain, matching.select)?,
+    };
+    if let Some(expected) = matching.expected_ip {
+        check_current_ip(&content, domain, target, expected, true)?;
+    }
+    // 只有找不到域名时才受 --if-exists 影响，正则等其他错误照常报告
+    let new_content = match remove_domain(&content, domain, target, verbose)? {
+        Some(new_content) => new_content,
+        None if matching.missing_ok => {
+            eprintln!("✅ 域名 '{}' 不存在，无需删除", domain);
+            return Ok(());
+        }
+        None => {
+            if matching.explain {
+                explain_no_match(&content, domain);
+            }
+            suggest_similar_domain(&content, domain);
+            anyhow::bail!("域名 '{}' 不存在，无需删除", domain);
+        }
+    };
+    
+    if matching.plan {
+        ChangeReport::between(&content, &new_content).print();
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    let previous = ChangeReport::between(&content, &new_content).previous_ips();
+    if previous.is_empty() {
+        eprintln!("✅ 已删除域名映射: {}", domain);
+    } else {
+        eprintln!("✅ 已删除域名映射: {}（原 IP {}）", domain, previous.join(", "));
+    }
+    Ok(())
+}
+
+/// 删除别名，保留规范名；canonical 指定时只处理该规范名所在的行
+fn delete_alias(alias: &str, canonical: Option<&str>, store: &dyn HostsStore, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    if verbose {
+        eprintln!("[verbose] 删除别名: {}", alias);
+    }
+    
+    let new_content = remove_alias(&content, alias, canonical, verbose)?;
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已删除别名: {}", alias);
+    Ok(())
+}
+
+/// delete --cidr/--tag 的删除范围
+struct DeleteScope<'a> {
+    /// 只删除 IP 位于该网段内的映射
+    cidr: Option<&'a Cidr>,
+    /// 只删除带有该分组标签的映射
+    tag: Option<&'a str>,
+}
+
+impl DeleteScope<'_> {
+    fn contains(&self, ip: &str, line: &str) -> bool {
+        self.cidr.is_none_or(|cidr| cidr.contains_str(ip))
+            && self.tag.is_none_or(|tag| line.find('#').and_then(|pos| tag_of(&line[pos..])) == Some(tag))
+    }
+}
+
+impl fmt::Display for DeleteScope<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.cidr, self.tag) {
+            (Some(cidr), Some(tag)) => write!(f, "{} 内标签为 {}", cidr, tag),
+            (Some(cidr), None) => write!(f, "{} 内", cidr),
+            (None, Some(tag)) => write!(f, "标签 {} 下", tag),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// 删除范围内的映射，domain 指定时只删除范围内该域名的映射
+fn delete_in_scope(domain: Option<&str>, scope: &DeleteScope, store: &dyn HostsStore, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    if verbose {
+        eprintln!("[verbose] 删除 {}的映射", scope);
+    }
+    
+    let domain_regex = domain
+        .map(domain_regex)
+        .transpose()?;
+    let mut removed = 0;
+    let new_content: String = content
+        .split_inclusive('\n')
+        .filter(|line| {
+            let matched = parse_mapping(line).is_some_and(|(ip, _)| scope.contains(ip, line))
+                && domain_regex.as_ref().is_none_or(|regex| regex.is_match(line));
+            if matched {
+                if verbose {
+                    eprintln!("[verbose] 删除行: {}", line.trim_end());
+                }
+                removed += 1;
+            }
+            !matched
+        })
+        .collect();
+    
+    if removed == 0 {
+        match domain {
+            Some(domain) => anyhow::bail!("{}不存在域名 '{}' 的映射，无需删除", scope, domain),
+            None => anyhow::bail!("{}没有任何映射，无需删除", scope),
+        }
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已删除 {}的 {} 条映射", scope, removed);
+    Ok(())
+}
+
+/// 创建新的域名映射
+fn create_domain(
+    domain: &str,
+    ips: &[String],
+    options: &CreateOptions,
+    store: &dyn HostsStore,
+    comments: &Comments,
+    backup: bool,
+    verbose: bool,
+) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    let ip = ips.join(", ");
+    if verbose {
+        eprintln!("[verbose] 创建域名映射: {} -> {}", domain, ip);
+    }
+    
+    let new_content = match add_new_domain(&content, domain, ips, options, comments, verbose) {
+        Err(e) if options.exists_ok && e.downcast_ref::<HostsError>().is_some() => {
+            eprintln!("✅ 域名 '{}' 已存在，未做修改", domain);
+            return Ok(());
+        }
+        result => result?,
+    };
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已创建域名映射: {} -> {}", domain, ip);
+    Ok(())
+}
+
+/// 停用或恢复域名映射
+fn toggle_domain(
+    domain: &str,
+    enable: bool,
+    reason: Option<&str>,
+    store: &dyn HostsStore,
+    backup: bool,
+    verbose: bool,
+) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    let new_content = if enable {
+        enable_domain_lines(&content, domain, verbose)?
+    } else {
+        disable_domain_lines(&content, domain, reason, verbose)?
+    };
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    if enable {
+        eprintln!("✅ 已恢复域名映射: {}", domain);
+    } else {
+        eprintln!("✅ 已停用域名映射: {}", domain);
+    }
+    Ok(())
+}
+
+/// 删除所有已过期的映射
+fn prune_expired(store: &dyn HostsStore, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    let ip_regex = ip_regex();
+    let expires_regex = expires_regex();
+    let now = Utc::now();
+    
+    let mut removed = 0;
+    let new_content: String = content
+        .split_inclusive('\n')
+        .filter(|line| {
+            let expired = ip_regex.is_match(line)
+                && expires_regex
+                    .captures(line)
+                    .and_then(|caps| NaiveDateTime::parse_from_str(&caps[1], EXPIRES_FORMAT).ok())
+                    .is_some_and(|expires| expires.and_utc() <= now);
+            if expired {
+                if verbose {
+                    eprintln!("[verbose] 删除过期行: {}", line);
+                }
+                removed += 1;
+            }
+            !expired
+        })
+        .collect();
+    
+    if removed == 0 {
+        eprintln!("✅ 没有过期的映射");
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已删除 {} 条过期映射", removed);
+    Ok(())
+}
+
+/// 当前平台全新安装时的 hosts 文件内容
+#[cfg(target_os = "windows")]
+fn default_hosts_content() -> &'static str {
+    "# Copyright (c) 1993-2009 Microsoft Corp.\r\n\
+     #\r\n\
+     # This is a sample HOSTS file used by Microsoft TCP/IP for Windows.\r\n\
+     #\r\n\
+     # localhost name resolution is handled within DNS itself.\r\n\
+     #\t127.0.0.1       localhost\r\n\
+     #\t::1             localhost\r\n"
+}
+
+/// 当前平台全新安装时的 hosts 文件内容
+#[cfg(target_os = "macos")]
+fn default_hosts_content() -> &'static str {
+    "##\n\
+     # Host Database\n\
+     #\n\
+     # localhost is used to configure the loopback interface\n\
+     # when the system is booting.  Do not change this entry.\n\
+     ##\n\
+     127.0.0.1\tlocalhost\n\
+     255.255.255.255\tbroadcasthost\n\
+     ::1             localhost\n"
+}
+
+/// 当前平台全新安装时的 hosts 文件内容
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn default_hosts_content() -> &'static str {
+    "127.0.0.1\tlocalhost\n\
+     ::1\t\tlocalhost ip6-localhost ip6-loopback\n\
+     ff02::1\t\tip6-allnodes\n\
+     ff02::2\t\tip6-allrouters\n"
+}
+
+/// 用平台默认内容覆盖 hosts 文件；未指定 confirm 时在终端中询问，非交互时拒绝执行
+fn reset_hosts(store: &dyn HostsStore, confirm: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    let default = default_hosts_content();
+    if content == default {
+        eprintln!("✅ hosts 文件已是默认内容，无需重置");
+        return Ok(());
+    }
+    
+    if !confirm {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("reset 会覆盖整个 hosts 文件，请加上 --confirm 确认");
+        }
+        eprint!("⚠️  将用系统默认内容覆盖 {}，确定继续吗？[y/N]: ", store.name());
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).context("读取输入失败")?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            eprintln!("已取消");
+            return Ok(());
+        }
+    }
+    
+    // 无论是否指定 --backup 都先备份
+    let backup_path = store.backup()?;
+    eprintln!("📋 原内容已备份到: {}", backup_path);
+    
+    if verbose {
+        eprintln!("[verbose] 写入默认内容: {} 行", default.lines().count());
+    }
+    store.write(default)?;
+    
+    eprintln!("✅ 已重置为系统默认内容: {}", store.name());
+    Ok(())
+}
+
+/// generate 使用的清单，如：
+///
+/// ```json
+/// {
+///   "header": ["由 infra/hosts.json 生成，请勿手动修改"],
+///   "order": "domain",
+///   "entries": [{ "ip": "127.0.0.1", "domain": "localhost" }],
+///   "sections": [
+///     { "name": "dev", "entries": [{ "ip": "10.0.0.5", "domain": "api.dev", "aliases": ["web.dev"], "comment": "测试环境" }] }
+///   ]
+/// }
+/// ```
+///
+/// 也可以写成等价的 YAML：
+///
+/// ```yaml
+/// header: [由 infra/hosts.yaml 生成，请勿手动修改]
+/// order: domain
+/// entries:
+///   - { ip: 127.0.0.1, domain: localhost }
+/// sections:
+///   - name: dev
+///     entries:
+///       - { ip: 10.0.0.5, domain: api.dev, aliases: [web.dev], comment: 测试环境 }
+/// ```
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    /// 文件开头的注释，每项一行
+    #[serde(default)]
+    header: Vec<String>,
+    /// 映射的默认排序规则，分区可单独覆盖
+    #[serde(default)]
+    order: ManifestOrder,
+    /// 不属于任何分区的映射，写在所有分区之前
+    #[serde(default)]
+    entries: Vec<ManifestEntry>,
+    /// 依次写为 `# BEGIN <名称>` / `# END <名称>` 分区
+    #[serde(default)]
+    sections: Vec<ManifestSection>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestSection {
+    name: String,
+    /// 写在 BEGIN 标记之后的注释
+    comment: Option<String>,
+    order: Option<ManifestOrder>,
+    #[serde(default)]
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    ip: IpAddr,
+    domain: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    comment: Option<String>,
+}
+
+/// 清单中映射的排序规则
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ManifestOrder {
+    /// 保持清单中的顺序
+    #[default]
+    Manifest,
+    /// 按域名排序
+    Domain,
+    /// 按 IP 排序
+    Ip,
+}
+
+impl Manifest {
+    /// 解析清单文本，yaml 为真时按 YAML 解析，否则按 JSON
+    fn parse(text: &str, yaml: bool) -> Result<Self> {
+        if yaml {
+            Ok(serde_yaml_ng::from_str(text)?)
+        } else {
+            Ok(serde_json::from_str(text)?)
+        }
+    }
+    
+    /// 渲染为 hosts 文件内容
+    fn render(&self) -> Result<String> {
+        let mut content = String::new();
+        for line in &self.header {
+            if line.is_empty() {
+                content.push_str("#\n");
+            } else {
+                content.push_str(&format!("# {}\n", line));
+            }
+        }
+        if !self.header.is_empty() {
+            content.push('\n');
+        }
+        
+        render_manifest_entries(&mut content, &self.entries, self.order)?;
+        for section in &self.sections {
+            if section.name.trim().is_empty() || section.name.contains('\n') {
+                anyhow::bail!("分区名称不能为空或包含换行: {:?}", section.name);
+            }
+            // 分区之间以空行分隔
+            if !content.is_empty() && !content.ends_with("\n\n") {
+                content.push('\n');
+            }
+            content.push_str(&format!("# BEGIN {}\n", section.name));
+            if let Some(comment) = &section.comment {
+                content.push_str(&format!("# {}\n", comment));
+            }
+            render_manifest_entries(&mut content, &section.entries, section.order.unwrap_or(self.order))?;
+            content.push_str(&format!("# END {}\n", section.name));
+        }
+        Ok(content)
+    }
+}
+
+/// 按排序规则写入映射行，域名和别名统一转换为 ASCII 形式
+fn render_manifest_entries(content: &mut String, entries: &[ManifestEntry], order: ManifestOrder) -> Result<()> {
+    let mut entries: Vec<&ManifestEntry> = entries.iter().collect();
+    match order {
+        ManifestOrder::Manifest => {}
+        ManifestOrder::Domain => entries.sort_by(|a, b| a.domain.cmp(&b.domain)),
+        ManifestOrder::Ip => entries.sort_by_key(|entry| entry.ip),
+    }
+    for entry in entries {
+        let mut line = format!("{} {}", entry.ip, ascii_domain(&entry.domain)?);
+        for alias in &entry.aliases {
+            line.push(' ');
+            line.push_str(&ascii_domain(alias)?);
+        }
+        if let Some(comment) = &entry.comment {
+            if comment.contains('\n') {
+                anyhow::bail!("{} 的注释不能包含换行", entry.domain);
+            }
+            line.push_str(&format!(" # {}", comment));
+        }
+        content.push_str(&line);
+        content.push('\n');
+    }
+    Ok(())
+}
+
+/// 按清单生成 hosts 文件并覆盖目标文件
+fn generate_hosts(manifest_path: &Path, store: &dyn HostsStore, dry_run: bool, color: bool, verbose: bool) -> Result<()> {
+    let text = fs::read_to_string(manifest_path)
+        .with_context(|| format!("无法读取清单文件: {}", manifest_path.display()))?;
+    let yaml = manifest_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+    let manifest = Manifest::parse(&text, yaml)
+        .with_context(|| format!("清单文件格式错误: {}", manifest_path.display()))?;
+    let generated = manifest.render()?;
+    if verbose {
+        eprintln!(
+            "[verbose] 清单包含 {} 个分区，生成 {} 行",
+            manifest.sections.len(),
+            generated.lines().count()
+        );
+    }
+    
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    if content == generated {
+        eprintln!("✅ hosts 文件已与清单一致，无需修改");
+        return Ok(());
+    }
+    
+    if dry_run {
+        eprintln!("🔍 预览变更（未写入文件）:");
+        for change in diff_lines(&content, &generated) {
+            eprintln!("  {}", colorize_diff_line(&change, color));
+        }
+        return Ok(());
+    }
+    
+    // 整个文件都会被替换，无论是否指定 --backup 都先备份
+    let backup_path = store.backup()?;
+    eprintln!("📋 原内容已备份到: {}", backup_path);
+    store.write(&generated)?;
+    
+    eprintln!("✅ 已按清单 {} 生成 hosts 文件: {}", manifest_path.display(), store.name());
+    Ok(())
+}
+
+/// 去掉由 hostm 管理的映射行，返回新内容和删除的行数；section 指定时只处理该分区，
+/// whole_section 为真时删除分区内的全部映射，否则只删除注释中带有 created/updated by hostm 的映射
+fn remove_managed_lines(content: &str, section: Option<&str>, whole_section: bool, verbose: bool) -> Result<(String, usize)> {
+    let range = match section {
+        Some(name) => match find_section(content, name) {
+            Some((begin, end)) => begin..end,
+            None => anyhow::bail!("分区 '{}' 不存在", name),
+        },
+        None => 0..content.len(),
+    };
+    
+    let mut new_content = String::with_capacity(content.len());
+    let mut removed = 0;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let managed = range.contains(&offset)
+            && ip_regex().is_match(line)
+            && (whole_section && section.is_some() || managed_regex().is_match(line));
+        offset += line.len();
+        if managed {
+            if verbose {
+                eprintln!("[verbose] 删除行: {}", line.trim_end());
+            }
+            removed += 1;
+        } else {
+            new_content.push_str(line);
+        }
+    }
+    Ok((new_content, removed))
+}
+
+/// 清除由 hostm 管理的全部映射，删除前需确认；section 指定时删除该分区内的全部映射
+fn clear_hosts(store: &dyn HostsStore, section: Option<&str>, yes: bool, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    let (new_content, removed) = remove_managed_lines(&content, section, true, verbose)?;
+    
+    if removed == 0 {
+        eprintln!("✅ 没有需要清除的映射");
+        return Ok(());
+    }
+    
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("clear 将删除 {} 条映射，请加上 --yes 确认", removed);
+        }
+        let scope = section.map_or("由 hostm 管理的".to_string(), |name| format!("分区 '{}' 中的", name));
+        eprint!("⚠️  将删除 {} 中{}共 {} 条映射，确定继续吗？[y/N]: ", store.name(), scope, removed);
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).context("读取输入失败")?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            eprintln!("已取消");
+            return Ok(());
+        }
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已清除 {} 条映射", removed);
+    Ok(())
+}
+
+/// 删除由 hostm 创建或更新的映射行；section 指定时只处理该分区
+fn trim_hosts(store: &dyn HostsStore, section: Option<&str>, dry_run: bool, backup: bool, color: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    let (new_content, removed) = remove_managed_lines(&content, section, false, verbose)?;
+    
+    if removed == 0 {
+        eprintln!("✅ 没有由 hostm 管理的映射");
+        return Ok(());
+    }
+    
+    if dry_run {
+        eprintln!("🔍 预览变更（未写入文件，共 {} 行）:", removed);
+        for change in diff_lines(&content, &new_content) {
+            eprintln!("  {}", colorize_diff_line(&change, color));
+        }
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已删除 {} 条由 hostm 管理的映射", removed);
+    Ok(())
+}
+
+/// 整理空白：连续空行合并为一行，去掉行尾空白和文件末尾的空行
+fn compact_hosts(store: &dyn HostsStore, dry_run: bool, backup: bool, color: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    let mut new_content = String::with_capacity(content.len());
+    let mut changes = 0;
+    let mut blank_run = 0;
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let newline = &line[body.len()..];
+        let trimmed = body.trim_end();
+        
+        if trimmed.is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                if verbose {
+                    eprintln!("[verbose] 删除多余的空行");
+                }
+                changes += 1;
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        
+        if trimmed.len() != body.len() {
+            if verbose {
+                eprintln!("[verbose] 去掉行尾空白: {}", trimmed);
+            }
+            changes += 1;
+        }
+        new_content.push_str(trimmed);
+        new_content.push_str(newline);
+    }
+    
+    // 去掉文件末尾的空行，保留最后一行的换行符
+    while new_content.ends_with("\n\n") || new_content.ends_with("\n\r\n") {
+        let cut = if new_content.ends_with("\r\n") { 2 } else { 1 };
+        new_content.truncate(new_content.len() - cut);
+        if verbose {
+            eprintln!("[verbose] 删除文件末尾的空行");
+        }
+        changes += 1;
+    }
+    
+    if changes == 0 {
+        eprintln!("✅ 无需整理");
+        return Ok(());
+    }
+    
+    if dry_run {
+        eprintln!("🔍 预览变更（未写入文件，共 {} 处）:", changes);
+        for change in diff_lines(&content, &new_content) {
+            eprintln!("  {}", colorize_diff_line(&change, color));
+        }
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已整理 {} 处空白", changes);
+    Ok(())
+}
+
+/// 合并同一域名的重复映射：保留最后出现的一处，从之前的行中移除该域名
+fn defrag_domains(store: &dyn HostsStore, force: bool, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    
+    // 记录每个域名出现的行和 IP，按首次出现的顺序
+    let mut occurrences: Vec<(&str, Vec<(usize, &str)>)> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (line_idx, line) in lines.iter().enumerate() {
+        let Some((ip, domains)) = parse_mapping(line) else {
+            continue;
+        };
+        for domain in domains {
+            let slot = *index.entry(domain).or_insert_with(|| {
+                occurrences.push((domain, Vec::new()));
+                occurrences.len() - 1
+            });
+            occurrences[slot].1.push((line_idx, ip));
+        }
+    }
+    
+    // 同一域名指向不同 IP 时，除非 --force，否则拒绝合并
+    let conflicts: Vec<&(&str, Vec<(usize, &str)>)> = occurrences
+        .iter()
+        .filter(|(_, places)| places.iter().any(|(_, ip)| *ip != places[0].1))
+        .collect();
+    if !conflicts.is_empty() {
+        let report = if force { "⚠️  以下域名指向不同 IP，将保留最后出现的映射:" } else { "❌ 以下域名指向不同 IP:" };
+        eprintln!("{}", report);
+        for (domain, places) in &conflicts {
+            let detail: Vec<String> = places.iter().map(|(line_idx, ip)| format!("{} (line {})", ip, line_idx + 1)).collect();
+            eprintln!("  {}: {}", domain, detail.join(", "));
+        }
+        if !force {
+            anyhow::bail!("存在 {} 个冲突的域名，使用 --force 以最后出现的映射为准合并", conflicts.len());
+        }
+    }
+    
+    // 每行需要移除的域名
+    let mut removals: HashMap<usize, Vec<&str>> = HashMap::new();
+    let mut merged = 0;
+    for (domain, places) in &occurrences {
+        let Some(((keep, _), earlier)) = places.split_last() else {
+            continue;
+        };
+        if earlier.is_empty() {
+            continue;
+        }
+        if verbose {
+            eprintln!("[verbose] 合并 {}: 保留 line {}，移除 {} 处", domain, keep + 1, earlier.len());
+        }
+        for (line_idx, _) in earlier {
+            removals.entry(*line_idx).or_default().push(domain);
+        }
+        merged += 1;
+    }
+    
+    if merged == 0 {
+        eprintln!("✅ 没有需要合并的重复映射");
+        return Ok(());
+    }
+    
+    let mut new_content = String::with_capacity(content.len());
+    for (line_idx, line) in lines.iter().enumerate() {
+        let Some(removed) = removals.get(&line_idx) else {
+            new_content.push_str(line);
+            continue;
+        };
+        let Some((ip, domains)) = parse_mapping(line) else {
+            continue;
+        };
+        let remaining: Vec<&str> = domains.into_iter().filter(|d| !removed.contains(d)).collect();
+        if remaining.is_empty() {
+            if verbose {
+                eprintln!("[verbose] 删除行: {}", line.trim_end());
+            }
+            continue;
+        }
+        
+        // 保留行内注释和原有的换行符
+        let body = line.trim_end_matches(['\r', '\n']);
+        let comment = body.find('#').map(|pos| format!(" {}", &body[pos..])).unwrap_or_default();
+        let rewritten = format!("{} {}{}", ip, remaining.join(" "), comment);
+        if verbose {
+            eprintln!("[verbose] 更新行: {} => {}", body, rewritten);
+        }
+        new_content.push_str(&rewritten);
+        new_content.push_str(&line[body.len()..]);
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已合并 {} 个域名的重复映射", merged);
+    Ok(())
+}
+
+/// 解析 30s、10m、2h、7d、1w 形式的时长
+fn parse_duration(s: &str) -> std::result::Result<TimeDelta, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: i64 = number.parse().map_err(|_| format!("无效的时长: {}", s))?;
+    
+    let duration = match unit {
+        "s" => TimeDelta::try_seconds(number),
+        "m" => TimeDelta::try_minutes(number),
+        "h" => TimeDelta::try_hours(number),
+        "d" => TimeDelta::try_days(number),
+        "w" => TimeDelta::try_weeks(number),
+        _ => return Err(format!("无效的时长单位: {}（支持 s/m/h/d/w）", s)),
+    };
+    duration.ok_or_else(|| format!("时长过长: {}", s))
+}
+
+/// 解析 --since：日期取当天 00:00:00
+fn parse_since(s: &str) -> std::result::Result<NaiveDateTime, String> {
+    parse_date_bound(s, NaiveTime::MIN)
+}
+
+/// 解析 --until：日期取当天 23:59:59
+fn parse_until(s: &str) -> std::result::Result<NaiveDateTime, String> {
+    parse_date_bound(s, NaiveTime::from_hms_opt(23, 59, 59).expect("时间无效"))
+}
+
+/// 解析 YYYY-MM-DD 或 YYYY-MM-DD HH:MM:SS，只给日期时使用 time
+fn parse_date_bound(s: &str, time: NaiveTime) -> std::result::Result<NaiveDateTime, String> {
+    let s = s.trim();
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|date| date.and_time(time)))
+        .map_err(|_| format!("无效的时间: {}（支持 YYYY-MM-DD 或 \"YYYY-MM-DD HH:MM:SS\"）", s))
+}
+
+/// 域名不以后缀结尾时追加后缀（create --domain-suffix），如 api + .project.local -> api.project.local
+fn with_domain_suffix(domain: &str, suffix: Option<&str>) -> String {
+    let Some(suffix) = suffix.map(|suffix| suffix.trim_start_matches('.')).filter(|suffix| !suffix.is_empty()) else {
+        return domain.to_string();
+    };
+    let domain = domain.trim_end_matches('.');
+    if domain == suffix || domain.ends_with(&format!(".{}", suffix)) {
+        domain.to_string()
+    } else {
+        format!("{}.{}", domain, suffix)
+    }
+}
+
+/// 将国际化域名转换为 ASCII 兼容编码（Punycode），如 münchen.de -> xn--mnchen-3ya.de；纯 ASCII 的域名原样返回
+fn ascii_domain(domain: &str) -> Result<Cow<'_, str>> {
+    if domain.is_ascii() {
+        return Ok(Cow::Borrowed(domain));
+    }
+    idna::domain_to_ascii(domain)
+        .map(Cow::Owned)
+        .map_err(|_| anyhow::anyhow!("无效的国际化域名: {}", domain))
+}
+
+/// 校验分组标签：不能为空，也不能包含空白或 #，以免破坏注释
+fn parse_tag(s: &str) -> std::result::Result<String, String> {
+    if s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '#') {
+        return Err(format!("无效的标签: '{}'（不能为空，也不能包含空白或 #）", s));
+    }
+    Ok(s.to_string())
+}
+
+/// 解析 10.0.0.0/8 形式的网段，省略前缀长度时视为单个地址
+fn parse_cidr(s: &str) -> std::result::Result<Cidr, String> {
+    let (addr, prefix) = s.split_once('/').unwrap_or((s, ""));
+    let network: IpAddr = addr.trim().parse().map_err(|_| format!("无效的网段地址: {}", s))?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = if prefix.is_empty() {
+        max
+    } else {
+        prefix.parse().map_err(|_| format!("无效的前缀长度: {}", s))?
+    };
+    if prefix > max {
+        return Err(format!("前缀长度超出范围: {}（最大 {}）", s, max));
+    }
+    Ok(Cidr { network, prefix })
+}
+
+/// IP 网段，用于按网络范围筛选映射
+#[derive(Clone, Copy)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// 判断地址是否位于网段内，地址族不同时视为不在网段内
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+    
+    /// 解析文本形式的 IP 后判断，无法解析时视为不在网段内
+    fn contains_str(&self, ip: &str) -> bool {
+        ip.parse().is_ok_and(|ip| self.contains(&ip))
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// search 的输出选项
+struct SearchOptions {
+    /// 以 "域名 -> IP (行号)" 的紧凑格式输出
+    summary: bool,
+    /// 最多输出的匹配数量，0 表示不限制
+    max_results: usize,
+    format: OutputFormat,
+    /// 只匹配 IP 位于该网段内的映射
+    scope: Option<Cidr>,
+    /// 只输出匹配数量
+    count_only: bool,
+    /// 每个匹配前显示的上下文行数
+    before: usize,
+    /// 每个匹配后显示的上下文行数
+    after: usize,
+}
+
+/// list 的输出选项
+struct ListOptions<'a> {
+    offset: usize,
+    /// 最多输出的映射数量，0 表示不限制
+    limit: usize,
+    align_columns: bool,
+    format: OutputFormat,
+    /// 只列出 IP 位于该网段内的映射
+    scope: Option<Cidr>,
+    /// 只列出由该用户创建或更新的映射
+    user: Option<&'a str>,
+    /// 只列出已验证（true）或未验证（false）的映射
+    trusted: Option<bool>,
+    /// 只列出带有该分组标签的映射
+    tag: Option<&'a str>,
+    /// 只列出由 hostm 管理（true）或手动添加（false）的映射
+    managed: Option<bool>,
+    /// 只列出屏蔽映射
+    blocked: bool,
+    /// 只列出 hostm 时间戳位于该区间内的映射
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    /// 按时间筛选时保留没有 hostm 时间戳的映射
+    include_undated: bool,
+    /// 只输出映射数量
+    count_only: bool,
+    /// 按列为行号、IP 和域名着色
+    color: bool,
+}
+
+impl ListOptions<'_> {
+    /// 映射是否满足 --cidr、--user 等筛选条件
+    fn matches(&self, entry: &HostEntry) -> bool {
+        self.scope.is_none_or(|cidr| cidr.contains_str(&entry.ip))
+            && self.user.is_none_or(|user| {
+                let signature = format!("by {} via hostm", user);
+                entry.comment.as_deref().is_some_and(|comment| comment.contains(&signature))
+            })
+            && self.trusted.is_none_or(|trusted| {
+                entry.comment.as_deref().is_some_and(|comment| comment.contains(TRUSTED_MARKER)) == trusted
+            })
+            && self.tag.is_none_or(|tag| entry.comment.as_deref().and_then(tag_of) == Some(tag))
+            && self.managed.is_none_or(|managed| {
+                entry.comment.as_deref().is_some_and(|comment| managed_regex().is_match(&format!("# {}", comment))) == managed
+            })
+            && (!self.blocked || is_blocking_entry(entry))
+            && self.in_time_range(entry)
+    }
+    
+    /// 映射的 hostm 时间戳是否满足 --since/--until
+    fn in_time_range(&self, entry: &HostEntry) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        match entry.comment.as_deref().and_then(hostm_timestamp) {
+            Some(timestamp) => {
+                self.since.is_none_or(|since| timestamp >= since) && self.until.is_none_or(|until| timestamp <= until)
+            }
+            None => self.include_undated,
+        }
+    }
+}
+
+/// 从注释中取出 hostm 写入的 created/updated 时间戳
+fn hostm_timestamp(comment: &str) -> Option<NaiveDateTime> {
+    static TIMESTAMP_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = TIMESTAMP_REGEX.get_or_init(|| {
+        Regex::new(r"(?:created|updated) by (?:\S+ via )?hostm (\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})")
+            .expect("hostm 时间戳正则无效")
+    });
+    let caps = regex.captures(comment)?;
+    NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d %H:%M:%S").ok()
+}
+
+/// search --format jsonl 输出的一条匹配
+#[derive(serde::Serialize)]
+struct SearchMatch<'a> {
+    source: String,
+    line: usize,
+    text: &'a str,
+    ip: Option<&'a str>,
+    domains: Vec<&'a str>,
+    canonical: Option<&'a str>,
+}
+
+/// 查找域名映射，返回匹配数量
+fn search_domain(
+    domain: &str,
+    stores: &[&dyn HostsStore],
+    options: &SearchOptions,
+    out: &mut Output,
+    verbose: bool,
+) -> Result<usize> {
+    let SearchOptions { summary, max_results, format, scope, count_only, before, after } = *options;
+    let with_context = before > 0 || after > 0;
+    
+    // 检查文件
+    for store in stores {
+        store.check()?;
+    }
+    
+    if verbose {
+        eprintln!("[verbose] 查找包含 '{}' 的行", domain);
+    }
+    
+    // 国际化域名同时按原文和 Punycode 形式匹配
+    let ace = ascii_domain(domain).ok().filter(|ace| ace != domain);
+    let matches_query = |text: &str| text.contains(domain) || ace.as_deref().is_some_and(|ace| text.contains(ace));
+    
+    // 多个文件时在每条结果前标注来源
+    let multiple = stores.len() > 1;
+    let mut found = false;
+    let mut count = 0;
+    'files: for store in stores {
+        // 逐行读取，避免将大文件整体载入内存
+        let reader = store.reader()?;
+        let source = if multiple { store.name() } else { String::new() };
+        // 上下文：尚未输出的前几行、还需输出的后续行数、最后输出的行号
+        let mut pending: VecDeque<(usize, String)> = VecDeque::with_capacity(before);
+        let mut after_left = 0;
+        let mut last_shown = None;
+        
+        for (line_num, line) in reader.lines().enumerate() {
+            let line = line.context("读取 hosts 文件失败")?;
+            let is_match = matches_query(&line)
+                && scope.is_none_or(|cidr| parse_mapping(&line).is_some_and(|(ip, _)| cidr.contains_str(ip)));
+            if !is_match {
+                if after_left > 0 {
+                    write_search_line(out, &source, line_num, '-', &line)?;
+                    last_shown = Some(line_num);
+                    after_left -= 1;
+                } else if before > 0 {
+                    if pending.len() == before {
+                        pending.pop_front();
+                    }
+                    pending.push_back((line_num, line));
+                }
+                continue;
+            }
+            let line = line.as_str();
+            
+            if max_results > 0 && count >= max_results {
+                if count_only {
+                    break 'files;
+                }
+                if format == OutputFormat::Jsonl {
+                    eprintln!("(已限制为 {} 条结果，使用 --max-results=0 查看全部)", max_results);
+                } else {
+                    writeln!(out, "(已限制为 {} 条结果，使用 --max-results=0 查看全部)", max_results)?;
+                }
+                break 'files;
+            }
+            
+            if count_only {
+                // 只计数时沿用 --summary 的规则，只统计能解析为映射的行
+                if !summary || parse_mapping(line).is_some() {
+                    count += 1;
+                }
+                continue;
+            }
+            
+            if format == OutputFormat::Jsonl {
+                // JSON Lines 模式每个匹配立即输出一行，不输出标题
+                let (ip, domains) = parse_mapping(line).unwrap_or(("", Vec::new()));
+                let item = SearchMatch {
+                    source: store.name(),
+                    line: line_num + 1,
+                    text: line,
+                    ip: (!ip.is_empty()).then_some(ip),
+                    canonical: domains.first().copied(),
+                    domains,
+                };
+                writeln!(out, "{}", serde_json::to_string(&item)?)?;
+                found = true;
+            } else if summary {
+                // 紧凑模式只输出能解析为映射的行
+                let Some((ip, domains)) = parse_mapping(line) else {
+                    continue;
+                };
+                let canonical = domains[0];
+                let matched: Vec<&str> = domains.iter().copied().filter(|d| matches_query(d)).collect();
+                let shown = if matched.is_empty() { domains } else { matched };
+                
+                if !found {
+                    writeln!(out, "🔍 找到包含 '{}' 的映射:", domain)?;
+                    found = true;
+                }
+                for d in shown {
+                    // 同一行中第一个域名是规范名，其余是别名
+                    let role = if d == canonical { String::new() } else { format!(" [{} 的别名]", canonical) };
+                    if multiple {
+                        writeln!(out, "  {} -> {}{} ({} line {})", d, ip, role, source, line_num + 1)?;
+                    } else {
+                        writeln!(out, "  {} -> {}{} (line {})", d, ip, role, line_num + 1)?;
+                    }
+                }
+            } else {
+                if !found {
+                    writeln!(out, "🔍 找到包含 '{}' 的行:", domain)?;
+                    found = true;
+                }
+                if with_context {
+                    // 与上一组不相邻时用 -- 分隔
+                    let first = pending.front().map_or(line_num, |(n, _)| *n);
+                    if last_shown.is_some_and(|last| first > last + 1) {
+                        writeln!(out, "  --")?;
+                    }
+                    for (n, context) in pending.drain(..) {
+                        write_search_line(out, &source, n, '-', &context)?;
+                    }
+                    last_shown = Some(line_num);
+                    after_left = after;
+                }
+                write_search_line(out, &source, line_num, ':', line)?;
+            }
+            count += 1;
+        }
+    }
+    
+    if count_only {
+        writeln!(out, "{}", count)?;
+        return Ok(count);
+    }
+    
+    if !found && format == OutputFormat::Text {
+        writeln!(out, "❌ 未找到包含 '{}' 的行", domain)?;
+    }
+    
+    Ok(count)
+}
+
+/// 列出域名映射，返回输出的映射数量
+fn list_domains(
+    stores: &[&dyn HostsStore],
+    options: &ListOptions,
+    out: &mut Output,
+    verbose: bool,
+) -> Result<usize> {
+    let ListOptions { offset, limit, align_columns, format, count_only, color, .. } = *options;
+    if format == OutputFormat::Jsonl && !count_only {
+        return stream_entries_jsonl(stores, options, out);
+    }
+    
+    let mut hosts = HostsFile::load(stores)?;
+    hosts.entries.retain(|entry| options.matches(entry));
+    let entries = hosts.entries_paged(offset, limit);
+    
+    if verbose {
+        eprintln!("[verbose] 共 {} 条映射，跳过 {} 条，显示 {} 条", hosts.entries.len(), offset, entries.len());
+    }
+    
+    if count_only {
+        writeln!(out, "{}", entries.len())?;
+        return Ok(entries.len());
+    }
+    
+    if entries.is_empty() {
+        writeln!(out, "❌ 没有可显示的映射")?;
+        return Ok(0);
+    }
+    
+    // 多个文件时行号前标注来源
+    let multiple = stores.len() > 1;
+    let labels: Vec<String> = entries
+        .iter()
+        .map(|e| if multiple { format!("{}:{}", e.source, e.line) } else { e.line.to_string() })
+        .collect();
+    
+    // 对齐时按本次输出中最长的行号和 IP 计算列宽
+    let (label_width, ip_width) = if align_columns {
+        (
+            labels.iter().map(|l| l.len()).max().unwrap_or(0),
+            entries.iter().map(|e| e.ip.len()).max().unwrap_or(0),
+        )
+    } else {
+        (0, 0)
+    };
+    
+    writeln!(out, "📋 域名映射:")?;
+    for (label, entry) in labels.iter().zip(entries) {
+        // 单文件时行号右对齐，多文件时来源左对齐
+        let label = if multiple {
+            format!("{:<w$}", label, w = label_width)
+        } else {
+            format!("{:>w$}", label, w = label_width)
+        };
+        // 先填充再着色，控制符不计入列宽
+        let ip = format!("{:<w$}", entry.ip, w = ip_width);
+        writeln!(
+            out,
+            "  {}: {} {}",
+            paint(&label, "90", color),
+            paint(&ip, "36", color),
+            paint(&entry.domains.join(" "), "32", color)
+        )?;
+    }
+    
+    Ok(entries.len())
+}
+
+/// 导出映射，语义相同的文件导出结果逐字节一致
+fn export_hosts(
+    stores: &[&dyn HostsStore],
+    format: ExportFormat,
+    keep_comments: bool,
+    out: &mut Output,
+    verbose: bool,
+) -> Result<()> {
+    let hosts = HostsFile::load(stores)?;
+    
+    if verbose {
+        eprintln!("[verbose] 导出 {} 条映射", hosts.entries.len());
+    }
+    
+    match format {
+        ExportFormat::Hosts => {
+            // 一行多个域名与多行单个域名等价，统一拆成每行一个域名
+            let mut lines: Vec<String> = hosts
+                .entries
+                .iter()
+                .flat_map(|entry| {
+                    let ip = canonical_ip(&entry.ip);
+                    entry.domains.iter().map(move |domain| {
+                        let mut line = format!("{} {}", ip, domain.to_ascii_lowercase());
+                        if keep_comments {
+                            if let Some(comment) = &entry.comment {
+                                line.push_str(" # ");
+                                line.push_str(comment);
+                            }
+                        }
+                        line
+                    })
+                })
+                .collect();
+            lines.sort();
+            lines.dedup();
+            
+            for line in lines {
+                writeln!(out, "{}", line)?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// IP 的标准写法，如 2001:0db8::0001 -> 2001:db8::1；保留 IPv6 的 %zone，无法解析时原样返回
+fn canonical_ip(ip: &str) -> String {
+    let (address, zone) = match ip.split_once('%') {
+        Some((address, zone)) => (address, Some(zone)),
+        None => (ip, None),
+    };
+    match (address.parse::<IpAddr>(), zone) {
+        (Ok(address), Some(zone)) => format!("{}%{}", address, zone),
+        (Ok(address), None) => address.to_string(),
+        (Err(_), _) => ip.to_string(),
+    }
+}
+
+/// 按 `sha256sum` 的格式输出每个 hosts 文件的 SHA-256
+fn print_checksums(stores: &[&dyn HostsStore], out: &mut Output) -> Result<()> {
+    for store in stores {
+        store.check()?;
+        writeln!(out, "{}  {}", store.checksum()?, store.name())?;
+    }
+    Ok(())
+}
+
+/// 检查运行环境并逐项输出结果，有问题时以非零状态码退出
+fn diagnose(hosts_files: &[PathBuf], out: &mut Output) -> Result<()> {
+    writeln!(
+        out,
+        "📋 hostm {}（{}/{}）",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )?;
+    
+    let mut problems = 0;
+    let mut report = |out: &mut Output, ok: bool, message: String| -> Result<()> {
+        if !ok {
+            problems += 1;
+        }
+        writeln!(out, "{} {}", if ok { "✅" } else { "❌" }, message)?;
+        Ok(())
+    };
+    
+    let mut writable = true;
+    for path in hosts_files {
+        writeln!(out, "🔍 {}", path.display())?;
+        if path.to_string_lossy().starts_with("ssh://") {
+            writeln!(out, "💡 远程文件，跳过本地检查")?;
+            continue;
+        }
+        
+        if let Err(e) = check_hosts_file(path, false) {
+            report(out, false, format!("{:#}", e))?;
+            continue;
+        }
+        report(out, true, "文件存在".to_string())?;
+        
+        let readable = fs::File::open(path);
+        report(out, readable.is_ok(), match &readable {
+            Ok(_) => "可读".to_string(),
+            Err(e) => format!("不可读: {}", e),
+        })?;
+        
+        // 以追加方式打开只检查权限，不会修改文件
+        let file_writable = fs::OpenOptions::new().append(true).open(path);
+        writable &= file_writable.is_ok();
+        report(out, file_writable.is_ok(), match &file_writable {
+            Ok(_) => "可写".to_string(),
+            Err(e) => format!("不可写: {}", e),
+        })?;
+        
+        // 备份文件写在 hosts 文件旁边，创建并立即删除一个探测文件来检查目录权限
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let probe = dir.join(format!(".hostm-diagnose-{}", std::process::id()));
+        let dir_writable = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+            .and_then(|_| fs::remove_file(&probe));
+        writable &= dir_writable.is_ok();
+        report(out, dir_writable.is_ok(), match &dir_writable {
+            Ok(_) => format!("备份目录可写: {}", dir.display()),
+            Err(e) => format!("备份目录不可写: {}（{}）", dir.display(), e),
+        })?;
+    }
+    
+    let escalation: Vec<&str> = ["sudo", "doas"]
+        .into_iter()
+        .filter(|program| find_in_path(program).is_some())
+        .collect();
+    if escalation.is_empty() {
+        writeln!(out, "⚠️  未找到 sudo 或 doas")?;
+    } else {
+        writeln!(out, "✅ 可用于提权: {}", escalation.join(", "))?;
+    }
+    if !writable {
+        match escalation.first() {
+            Some(program) => writeln!(out, "💡 没有写权限时可使用 {} hostm <命令> 运行", program)?,
+            None if cfg!(target_os = "windows") => writeln!(out, "💡 没有写权限时请以管理员身份运行终端")?,
+            None => writeln!(out, "💡 没有写权限时请切换到 root 用户运行")?,
+        }
+    }
+    
+    if problems > 0 {
+        anyhow::bail!("发现 {} 个问题", problems);
+    }
+    writeln!(out, "✅ 未发现问题")?;
+    Ok(())
+}
+
+/// 在 PATH 中查找可执行文件
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(format!("{}{}", program, std::env::consts::EXE_SUFFIX)))
+        .find(|path| path.is_file())
+}
+
+/// 输出域名所在的映射，每个文件只输出第一处；返回是否找到
+fn show_domain(stores: &[&dyn HostsStore], domain: &str, format: OutputFormat, out: &mut Output) -> Result<bool> {
+    let mut found = false;
+    let mut contents = String::new();
+    for store in stores {
+        store.check()?;
+        let content = store.read()?;
+        let entry = find_domain(&content, domain);
+        contents.push_str(&content);
+        contents.push('\n');
+        let Some(mut entry) = entry else {
+            continue;
+        };
+        entry.source = store.name();
+        found = true;
+        match format {
+            OutputFormat::Jsonl => writeln!(out, "{}", serde_json::to_string(&entry)?)?,
+            OutputFormat::Text => {
+                write!(out, "📋 {} 第 {} 行: {} {}", entry.source, entry.line, entry.ip, entry.domains.join(" "))?;
+                match &entry.comment {
+                    Some(comment) => writeln!(out, " # {}", comment)?,
+                    None => writeln!(out)?,
+                }
+            }
+        }
+    }
+    if !found && format == OutputFormat::Text {
+        writeln!(out, "❌ 未找到域名: {}", domain)?;
+        suggest_similar_domain(&contents, domain);
+    }
+    Ok(found)
+}
+
+/// 输出分区内容；指定多个文件时依次输出各文件中的同名分区
+fn show_section(stores: &[&dyn HostsStore], name: &str, format: SectionFormat, out: &mut Output, verbose: bool) -> Result<()> {
+    let mut entries = Vec::new();
+    let mut found = false;
+    for store in stores {
+        store.check()?;
+        let content = store.read()?;
+        let Some((begin, end)) = find_section(&content, name) else {
+            continue;
+        };
+        found = true;
+        
+        // 分区内容从 BEGIN 标记的下一行开始
+        let body_start = begin + content[begin..].find('\n').map_or(content.len() - begin, |pos| pos + 1);
+        let first_line = content[..body_start].matches('\n').count() + 1;
+        if verbose {
+            eprintln!("[verbose] {} 中的分区 {} 从第 {} 行开始", store.name(), name, first_line);
+        }
+        
+        let source = store.name();
+        for (i, line) in content[body_start..end].lines().enumerate() {
+            match format {
+                SectionFormat::Text => writeln!(out, "{}", line)?,
+                SectionFormat::Json | SectionFormat::Hosts => entries.extend(HostEntry::parse(line, &source, first_line + i)),
+            }
+        }
+    }
+    
+    if !found {
+        anyhow::bail!("分区 '{}' 不存在", name);
+    }
+    
+    match format {
+        SectionFormat::Text => {}
+        SectionFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string(&entries)?)?;
+        }
+        SectionFormat::Hosts => {
+            for entry in &entries {
+                writeln!(out, "{} {}", entry.ip, entry.domains.join(" "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 重命名分区
+fn rename_section(old: &str, new: &str, store: &dyn HostsStore, backup: bool, verbose: bool) -> Result<()> {
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    if verbose {
+        eprintln!("[verbose] 重命名分区: {} -> {}", old, new);
+    }
+    
+    let new_content = rename_section_markers(&content, old, new, verbose)?;
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!("✅ 已重命名分区: {} -> {}", old, new);
+    Ok(())
+}
+
+/// 将分区的 BEGIN/END 标记替换为新名称，保留标记行的缩进和换行符
+fn rename_section_markers(content: &str, old: &str, new: &str, verbose: bool) -> Result<String> {
+    let Some((begin, end)) = find_section(content, old) else {
+        anyhow::bail!("分区 '{}' 不存在", old);
+    };
+    if find_section(content, new).is_some() {
+        anyhow::bail!("分区 '{}' 已存在", new);
+    }
+    
+    let mut result = String::with_capacity(content.len() + 2 * new.len());
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let marker = if offset == begin {
+            Some(format!("# BEGIN {}", new))
+        } else if offset == end {
+            Some(format!("# END {}", new))
+        } else {
+            None
+        };
+        match marker {
+            Some(marker) => {
+                let body = line.trim_end_matches(['\r', '\n']);
+                let indent = &body[..body.len() - body.trim_start().len()];
+                if verbose {
+                    eprintln!("[verbose] 更新行: {} => {}{}", body, indent, marker);
+                }
+                result.push_str(indent);
+                result.push_str(&marker);
+                result.push_str(&line[body.len()..]);
+            }
+            None => result.push_str(line),
+        }
+        offset += line.len();
+    }
+    
+    Ok(result)
+}
+
+/// docker sync 写入的映射在注释中的标记
+const DOCKER_MARKER: &str = "# managed by hostm-docker";
+
+/// 按运行中的 Docker 容器同步映射
+fn docker_sync(network: Option<&str>, store: &dyn HostsStore, backup: bool, verbose: bool) -> Result<()> {
+    // 先查询容器，连接 Docker 失败时不读写 hosts 文件
+    let containers = docker_containers(network, verbose)?;
+    
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    let (new_content, stats) = sync_docker_entries(&content, &containers, verbose);
+    
+    if stats == DockerSyncStats::default() {
+        eprintln!("✅ 映射已与 {} 个运行中的容器一致，无需修改", containers.len());
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if backup {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    eprintln!(
+        "✅ 已同步 Docker 容器映射: 新增 {}，更新 {}，删除 {}",
+        stats.added, stats.updated, stats.removed
+    );
+    Ok(())
+}
+
+/// docker sync 对映射的改动数量
+#[derive(Default, PartialEq)]
+struct DockerSyncStats {
+    added: usize,
+    updated: usize,
+    removed: usize,
+}
+
+/// 用容器列表（容器名, IP）更新带有 DOCKER_MARKER 的映射：IP 变化的改写，列表中没有的删除，缺少的追加到文件末尾
+fn sync_docker_entries(content: &str, containers: &[(String, String)], verbose: bool) -> (String, DockerSyncStats) {
+    let mut pending: HashMap<&str, &str> = containers.iter().map(|(name, ip)| (name.as_str(), ip.as_str())).collect();
+    let mut stats = DockerSyncStats::default();
+    let mut result = String::with_capacity(content.len());
+    
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let managed = body.contains(DOCKER_MARKER).then(|| parse_mapping(body)).flatten();
+        let Some((old_ip, domains)) = managed else {
+            result.push_str(line);
+            continue;
+        };
+        match pending.remove(domains[0]) {
+            Some(ip) if ip == old_ip => result.push_str(line),
+            Some(ip) => {
+                let updated = format!("{} {} {}", ip, domains[0], DOCKER_MARKER);
+                if verbose {
+                    eprintln!("[verbose] 更新行: {} => {}", body, updated);
+                }
+                result.push_str(&updated);
+                result.push_str(&line[body.len()..]);
+                stats.updated += 1;
+            }
+            None => {
+                if verbose {
+                    eprintln!("[verbose] 删除行: {}", body);
+                }
+                stats.removed += 1;
+            }
+        }
+    }
+    
+    for (name, ip) in containers {
+        if !pending.contains_key(name.as_str()) {
+            continue;
+        }
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        let line = format!("{} {} {}", ip, name, DOCKER_MARKER);
+        if verbose {
+            eprintln!("[verbose] 添加行: {}", line);
+        }
+        result.push_str(&line);
+        result.push('\n');
+        stats.added += 1;
+    }
+    
+    (result, stats)
+}
+
+/// 通过 Docker Engine API 列出运行中的容器，返回按名称排序的（容器名, IP）
+#[cfg(feature = "docker")]
+fn docker_containers(network: Option<&str>, verbose: bool) -> Result<Vec<(String, String)>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("无法创建异步运行时")?;
+    let summaries = runtime.block_on(async {
+        let docker = bollard::Docker::connect_with_local_defaults().context("无法连接 Docker")?;
+        docker.list_containers(None).await.context("无法列出 Docker 容器")
+    })?;
+    
+    let mut containers = Vec::new();
+    for summary in summaries {
+        let Some(name) = summary.names.iter().flatten().next().map(|name| name.trim_start_matches('/').to_string()) else {
+            continue;
+        };
+        let networks = summary.network_settings.and_then(|settings| settings.networks).unwrap_or_default();
+        let ip_of = |name: &str| {
+            networks.get(name).and_then(|endpoint| endpoint.ip_address.clone()).filter(|ip| !ip.is_empty())
+        };
+        // 未指定网络时优先使用 bridge，容器不在 bridge 上时使用名称最小的网络
+        let ip = match network {
+            Some(network) => ip_of(network),
+            None => ip_of("bridge").or_else(|| {
+                let mut names: Vec<&String> = networks.keys().collect();
+                names.sort();
+                names.into_iter().find_map(|name| ip_of(name))
+            }),
+        };
+        match ip {
+            Some(ip) => {
+                if verbose {
+                    eprintln!("[verbose] 容器: {} -> {}", name, ip);
+                }
+                containers.push((name, ip));
+            }
+            None if verbose => eprintln!("[verbose] 跳过没有可用 IP 的容器: {}", name),
+            None => {}
+        }
+    }
+    containers.sort();
+    Ok(containers)
+}
+
+#[cfg(not(feature = "docker"))]
+fn docker_containers(_network: Option<&str>, _verbose: bool) -> Result<Vec<(String, String)>> {
+    anyhow::bail!("不支持 docker 命令，请使用 --features docker 重新编译 hostm")
+}
+
+/// import-zone 的执行选项
+struct ImportOptions<'a> {
+    /// 展开 @ 和相对名称时使用的域
+    origin: Option<&'a str>,
+    /// 只预览变更，不写入文件
+    dry_run: bool,
+    /// 预览变更时是否使用颜色
+    color: bool,
+}
+
+/// 将 zone 文件中的 A/AAAA 记录合并为映射，所有改动在内存中完成，最后只写入一次
+fn import_zone(
+    zone_file: &Path,
+    options: &ImportOptions,
+    store: &dyn HostsStore,
+    backup_ops: &[Operation],
+    comments: &Comments,
+    verbose: bool,
+) -> Result<()> {
+    let ImportOptions { origin, dry_run, color } = *options;
+    let zone = fs::read_to_string(zone_file)
+        .with_context(|| format!("无法读取 zone 文件: {}", zone_file.display()))?;
+    let records = parse_zone(&zone, origin, verbose)?;
+    
+    // 同名的记录合并为一条映射，hosts 中每个地址族只保留第一个地址
+    let mut mappings: Vec<(String, Vec<IpAddr>)> = Vec::new();
+    for record in records {
+        let index = match mappings.iter().position(|(name, _)| name.eq_ignore_ascii_case(&record.name)) {
+            Some(index) => index,
+            None => {
+                mappings.push((record.name.clone(), Vec::new()));
+                mappings.len() - 1
+            }
+        };
+        let ips = &mut mappings[index].1;
+        if ips.iter().any(|ip| ip.is_ipv6() == record.ip.is_ipv6()) {
+            eprintln!("⚠️  第 {} 行: {} 已有同一地址族的地址，忽略 {}", record.line, record.name, record.ip);
+        } else {
+            ips.push(record.ip);
+        }
+    }
+    
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    let mut new_content = content.clone();
+    let mut applied = Vec::new();
+    let mut unchanged = 0;
+    for (name, ips) in &mappings {
+        let ips: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+        let Some(existing) = find_domain(&new_content, name) else {
+            new_content = add_new_domain(&new_content, name, &ips, &CreateOptions::default(), comments, verbose)
+                .with_context(|| format!("无法导入 {}", name))?;
+            eprintln!("  ✅ create {} -> {}", name, ips.join(", "));
+            applied.push(Operation::Create);
+            continue;
+        };
+        
+        // 已有双栈映射时按地址族分别更新，否则只更新与现有 IP 同一地址族（或第一个）的地址
+        let targets: Vec<(usize, &String)> = match dual_stack_lines(&new_content, name)? {
+            Some((v4, v6)) => ips.iter().map(|ip| (if ip.contains(':') { v6 } else { v4 }, ip)).collect(),
+            None => {
+                let same_family = ips.iter().find(|ip| ip.contains(':') == existing.ip.contains(':'));
+                vec![(existing.line - 1, same_family.unwrap_or(&ips[0]))]
+            }
+        };
+        let mut updated = false;
+        for (line_idx, ip) in targets {
+            let current = new_content.lines().nth(line_idx).and_then(parse_mapping).map(|(ip, _)| ip.to_string());
+            if current.as_deref() == Some(ip.as_str()) {
+                continue;
+            }
+            new_content = update_existing_domain(&new_content, name, ip, comments, Some(line_idx), verbose)
+                .with_context(|| format!("无法导入 {}", name))?;
+            eprintln!("  ✅ update {}: {} -> {}", name, current.unwrap_or_default(), ip);
+            updated = true;
+        }
+        if updated {
+            applied.push(Operation::Update);
+        } else {
+            unchanged += 1;
+        }
+    }
+    
+    if applied.is_empty() {
+        eprintln!("✅ 映射已与 zone 文件一致，无需修改（共 {} 个域名）", unchanged);
+        return Ok(());
+    }
+    
+    if dry_run {
+        eprintln!("🔍 预览变更（未写入文件）:");
+        for change in diff_lines(&content, &new_content) {
+            eprintln!("  {}", colorize_diff_line(&change, color));
+        }
+        return Ok(());
+    }
+    
+    // 写入前备份
+    if applied.iter().any(|op| backup_ops.contains(op)) {
+        store.backup()?;
+    }
+    
+    // 写入文件
+    store.write(&new_content)?;
+    
+    let created = applied.iter().filter(|op| **op == Operation::Create).count();
+    eprintln!(
+        "✅ 已从 zone 文件导入: 新增 {}，更新 {}，未变 {}",
+        created,
+        applied.len() - created,
+        unchanged
+    );
+    Ok(())
+}
+
+/// zone 文件中的一条 A/AAAA 记录
+struct ZoneRecord {
+    /// 所在行号（从 1 开始）
+    line: usize,
+    /// 展开后的完整域名，不含末尾的点
+    name: String,
+    ip: IpAddr,
+}
+
+/// 解析 BIND 格式的 zone 文件，只返回 A/AAAA 记录；支持 $ORIGIN、括号跨行、省略名称/TTL/class 的写法
+fn parse_zone(zone: &str, origin: Option<&str>, verbose: bool) -> Result<Vec<ZoneRecord>> {
+    // 去掉 ; 注释，按括号把跨行的记录合并为一条：(行号, 是否以空白开头, 字段)
+    let mut entries: Vec<(usize, bool, Vec<&str>)> = Vec::new();
+    let mut depth = 0usize;
+    for (line_idx, line) in zone.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default();
+        let tokens: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == '(' || c == ')').filter(|t| !t.is_empty()).collect();
+        let continued = depth > 0;
+        depth = (depth + line.matches('(').count()).saturating_sub(line.matches(')').count());
+        match entries.last_mut() {
+            Some((_, _, fields)) if continued => fields.extend(tokens),
+            _ if !tokens.is_empty() => entries.push((line_idx + 1, line.starts_with([' ', '\t']), tokens)),
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        anyhow::bail!("zone 文件中的括号没有闭合");
+    }
+    
+    let mut origin = origin.map(|origin| origin.trim_end_matches('.').to_string());
+    let mut owner: Option<String> = None;
+    let mut records = Vec::new();
+    for (line, indented, fields) in entries {
+        match fields[0] {
+            "$ORIGIN" => {
+                let name = fields.get(1).ok_or_else(|| anyhow::anyhow!("第 {} 行: $ORIGIN 缺少域名", line))?;
+                let expanded = expand_zone_name(name, origin.as_deref()).with_context(|| format!("第 {} 行", line))?;
+                if verbose {
+                    eprintln!("[verbose] $ORIGIN: {}", expanded);
+                }
+                origin = Some(expanded);
+                continue;
+            }
+            "$TTL" => continue,
+            directive if directive.starts_with('$') => {
+                eprintln!("⚠️  第 {} 行: 不支持的指令 {}，已跳过", line, directive);
+                continue;
+            }
+            _ => {}
+        }
+        
+        // 以空白开头的记录沿用上一条记录的名称
+        let rest = if indented {
+            &fields[..]
+        } else {
+            owner = Some(expand_zone_name(fields[0], origin.as_deref()).with_context(|| format!("第 {} 行", line))?);
+            &fields[1..]
+        };
+        let Some(name) = owner.clone() else {
+            anyhow::bail!("第 {} 行: 记录缺少名称", line);
+        };
+        
+        // 名称之后依次是可选的 TTL 和 class（顺序不限），然后是记录类型和数据
+        let is_ttl = |field: &str| field.starts_with(|c: char| c.is_ascii_digit()) && field.chars().all(|c| c.is_ascii_alphanumeric());
+        let is_class = |field: &str| ["IN", "CH", "HS", "CS"].iter().any(|class| field.eq_ignore_ascii_case(class));
+        let mut rest = rest.iter().skip_while(|field| is_ttl(field) || is_class(field));
+        let Some(record_type) = rest.next() else {
+            anyhow::bail!("第 {} 行: 记录缺少类型", line);
+        };
+        let data = rest.next().copied().unwrap_or_default();
+        
+        let ip = match record_type.to_ascii_uppercase().as_str() {
+            "A" => data.parse::<Ipv4Addr>().map(IpAddr::V4).ok(),
+            "AAAA" => data.parse::<Ipv6Addr>().map(IpAddr::V6).ok(),
+            other => {
+                eprintln!("⚠️  第 {} 行: 跳过不支持的记录类型 {}（{}）", line, other, name);
+                continue;
+            }
+        };
+        let Some(ip) = ip else {
+            anyhow::bail!("第 {} 行: 无效的 {} 记录地址: {}", line, record_type, data);
+        };
+        if name.starts_with('*') {
+            eprintln!("⚠️  第 {} 行: hosts 文件不支持通配符名称，已跳过 {}", line, name);
+            continue;
+        }
+        if verbose {
+            eprintln!("[verbose] 第 {} 行: {} {} {}", line, name, record_type, ip);
+        }
+        records.push(ZoneRecord { line, name, ip });
+    }
+    
+    Ok(records)
+}
+
+/// 按 origin 展开 zone 文件中的名称：@ 即 origin，以点结尾的是完整域名，其余为相对名称
+fn expand_zone_name(name: &str, origin: Option<&str>) -> Result<String> {
+    if let Some(absolute) = name.strip_suffix('.') {
+        return Ok(absolute.to_string());
+    }
+    let Some(origin) = origin else {
+        anyhow::bail!("无法展开名称 '{}'，请使用 --origin 或在 zone 文件中指定 $ORIGIN", name);
+    };
+    Ok(match (name, origin) {
+        ("@", "") => anyhow::bail!("根域名不能写入 hosts 文件"),
+        ("@", origin) => origin.to_string(),
+        (name, "") => name.to_string(),
+        (name, origin) => format!("{}.{}", name, origin),
+    })
+}
+
+/// 输出 search 的一行结果，sep 为 ':' 表示匹配行，'-' 表示上下文行；source 为空时不标注来源
+fn write_search_line(out: &mut Output, source: &str, line_num: usize, sep: char, line: &str) -> Result<()> {
+    if source.is_empty() {
+        writeln!(out, "  {}{} {}", line_num + 1, sep, line)?;
+    } else {
+        writeln!(out, "  {}:{}{} {}", source, line_num + 1, sep, line)?;
+    }
+    Ok(())
+}
+
+/// 按字典序输出以 prefix 开头的域名（含别名），每行一个，去重
+fn complete_domains(stores: &[&dyn HostsStore], prefix: &str, out: &mut Output) -> Result<()> {
+    let mut domains = Vec::new();
+    for store in stores {
+        for line in store.reader()?.lines() {
+            let line = line.context("读取 hosts 文件失败")?;
+            if let Some((_, names)) = parse_mapping(&line) {
+                domains.extend(names.into_iter().filter(|name| name.starts_with(prefix)).map(str::to_string));
+            }
+        }
+    }
+    
+    domains.sort();
+    domains.dedup();
+    for domain in domains {
+        writeln!(out, "{}", domain)?;
+    }
+    Ok(())
+}
+
+/// -f/--hosts-file 补全时额外提示的常见路径，可包含通配符
+const HOSTS_FILE_CANDIDATES: [&str; 3] = ["/etc/hosts", "/etc/hosts.d/*", "/tmp/hosts"];
+
+const BASH_COMPLETION: &str = r#"# hostm 的 bash 补全，使用方法: source <(hostm completions bash)
+_hostm() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" cmd="" word i positional=0
+    local -a hosts=() candidates=()
+    case "$prev" in
+        -f|--hosts-file)
+            # 未匹配的通配符保持原样，需要过滤掉
+            for word in @HOSTS_FILES@; do
+                [[ $word == *\** ]] || candidates+=("$word")
+            done
+            COMPREPLY=($(compgen -W "${candidates[*]}" -- "$cur") $(compgen -f -- "$cur"))
+            return ;;
+        @VALUE_OPTIONS@) return ;;
+    esac
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${COMP_WORDS[i]}"
+        case "$word" in
+            -f|--hosts-file) hosts+=(-f "${COMP_WORDS[i+1]}"); ((i++)); continue ;;
+            @VALUE_OPTIONS@) ((i++)); continue ;;
+            -*) continue ;;
+        esac
+        if [[ -z $cmd ]]; then cmd="$word"; else ((positional++)); fi
+    done
+    if [[ -z $cmd ]]; then
+        COMPREPLY=($(compgen -W "@SUBCOMMANDS@" -- "$cur"))
+    elif [[ $cur != -* && $positional -eq 0 ]]; then
+        case "$cmd" in
+            @DOMAIN_COMMANDS@) COMPREPLY=($(hostm "${hosts[@]}" __complete-domains "$cur" 2>/dev/null)) ;;
+        esac
+    fi
+}
+complete -o default -F _hostm hostm
+"#;
+
+const ZSH_COMPLETION: &str = r#"#compdef hostm
+# hostm 的 zsh 补全，使用方法: source <(hostm completions zsh)
+_hostm() {
+    local cmd="" word i positional=0
+    local -a hosts domains
+    case ${words[CURRENT-1]} in
+        (-f|--hosts-file) compadd -- @HOSTS_FILES@; _files; return ;;
+        (@VALUE_OPTIONS@) _files; return ;;
+    esac
+    for ((i = 2; i < CURRENT; i++)); do
+        word=${words[i]}
+        case $word in
+            (-f|--hosts-file) hosts+=(-f ${words[i+1]}); ((i++)); continue ;;
+            (@VALUE_OPTIONS@) ((i++)); continue ;;
+            (-*) continue ;;
+        esac
+        if [[ -z $cmd ]]; then cmd=$word; else ((positional++)); fi
+    done
+    if [[ -z $cmd ]]; then
+        compadd -- @SUBCOMMANDS@
+    elif [[ $PREFIX != -* && $positional -eq 0 && $cmd == (@DOMAIN_COMMANDS@) ]]; then
+        domains=(${(f)"$(hostm $hosts __complete-domains $PREFIX 2>/dev/null)"})
+        compadd -- ${domains:#}
+    else
+        _files
+    fi
+}
+compdef _hostm hostm
+"#;
+
+const FISH_COMPLETION: &str = r#"# hostm 的 fish 补全，使用方法: hostm completions fish | source
+function __hostm_complete_domains
+    set -l tokens (commandline -opc)
+    set -l hosts
+    for i in (seq 2 (count $tokens))
+        if contains -- $tokens[$i] -f --hosts-file; and test $i -lt (count $tokens)
+            set hosts $hosts -f $tokens[(math $i + 1)]
+        end
+    end
+    hostm $hosts __complete-domains (commandline -ct) 2>/dev/null
+end
+function __hostm_hosts_files
+    # for 中未匹配的通配符展开为空
+    for path in @HOSTS_FILES@
+        echo $path
+    end
+end
+complete -c hostm -s f -l hosts-file -r -F -a "(__hostm_hosts_files)"
+complete -c hostm -n __fish_use_subcommand -f -a "@SUBCOMMANDS@"
+complete -c hostm -n "__fish_seen_subcommand_from @DOMAIN_COMMANDS@" -f -a "(__hostm_complete_domains)"
+"#;
+
+/// 根据命令行定义生成补全脚本：补全子命令名，已有域名参数的位置调用 __complete-domains
+fn completion_script(shell: CompletionShell) -> String {
+    let command = Args::command();
+    let subcommands: Vec<&str> = command
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .map(|sub| sub.get_name())
+        .collect();
+    // create 的域名是新域名，补全已有域名没有意义
+    let domain_commands: Vec<&str> = command
+        .get_subcommands()
+        .filter(|sub| sub.get_name() != "create")
+        .filter(|sub| sub.get_positionals().any(|arg| arg.get_id() == "domain"))
+        .map(|sub| sub.get_name())
+        .collect();
+    // 带值的选项：扫描命令行时跳过它们的值，避免把值当成子命令或域名
+    let mut value_options: Vec<String> = std::iter::once(&command)
+        .chain(command.get_subcommands())
+        .flat_map(|sub| sub.get_opts())
+        .filter(|arg| arg.get_action().takes_values())
+        .flat_map(|arg| {
+            let short = arg.get_short().map(|short| format!("-{}", short));
+            let long = arg.get_long().map(|long| format!("--{}", long));
+            short.into_iter().chain(long)
+        })
+        .collect();
+    value_options.sort();
+    value_options.dedup();
+    let (template, separator) = match shell {
+        CompletionShell::Bash => (BASH_COMPLETION, "|"),
+        CompletionShell::Zsh => (ZSH_COMPLETION, "|"),
+        CompletionShell::Fish => (FISH_COMPLETION, " "),
+    };
+    let hosts_files: Vec<String> = HOSTS_FILE_CANDIDATES
+        .iter()
+        .map(|path| match shell {
+            // zsh 的 (N) 使未匹配的通配符展开为空
+            CompletionShell::Zsh if path.contains('*') => format!("{}(N)", path),
+            _ => path.to_string(),
+        })
+        .collect();
+    template
+        .replace("@HOSTS_FILES@", &hosts_files.join(" "))
+        .replace("@SUBCOMMANDS@", &subcommands.join(" "))
+        .replace("@DOMAIN_COMMANDS@", &domain_commands.join(separator))
+        .replace("@VALUE_OPTIONS@", &value_options.join("|"))
+}
+
+/// 批量文件中的一条操作
+enum BatchOp {
+    Create { domain: String, ip: String },
+    Update { domain: String, ip: String },
+    Delete { domain: String },
+    Disable { domain: String, reason: Option<String> },
+    Enable { domain: String },
+    /// unified diff 中的一个 hunk
+    Hunk(PatchHunk),
+}
+
+impl BatchOp {
+    /// 解析一行批量操作
+    fn parse(line: &str) -> Result<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if let ["create" | "update", _, ip] = tokens.as_slice() {
+            ip.parse::<IpAddr>().map_err(|_| anyhow::anyhow!("无效的 IP 地址: {}", ip))?;
+        }
+        match tokens.as_slice() {
+            ["create", domain, ip] => Ok(BatchOp::Create { domain: domain.to_string(), ip: ip.to_string() }),
+            ["update", domain, ip] => Ok(BatchOp::Update { domain: domain.to_string(), ip: ip.to_string() }),
+            ["delete", domain] => Ok(BatchOp::Delete { domain: domain.to_string() }),
+            ["disable", domain, reason @ ..] => Ok(BatchOp::Disable {
+                domain: domain.to_string(),
+                reason: (!reason.is_empty()).then(|| reason.join(" ")),
+            }),
+            ["enable", domain] => Ok(BatchOp::Enable { domain: domain.to_string() }),
+            _ => anyhow::bail!("无法识别的操作: {}", line),
+        }
+    }
+    
+    fn operation(&self) -> Operation {
+        match self {
+            BatchOp::Create { .. } => Operation::Create,
+            BatchOp::Update { .. } => Operation::Update,
+            BatchOp::Delete { .. } => Operation::Delete,
+            BatchOp::Disable { .. } | BatchOp::Enable { .. } | BatchOp::Hunk(_) => Operation::Update,
+        }
+    }
+    
+    /// 在内存中对内容执行该操作
+    fn apply(&self, content: &str, comments: &Comments, verbose: bool) -> Result<String> {
+        match self {
+            BatchOp::Create { domain, ip } => {
+                add_new_domain(content, domain, std::slice::from_ref(ip), &CreateOptions::default(), comments, verbose)
+            }
+            BatchOp::Update { domain, ip } => update_existing_domain(content, domain, ip, comments, None, verbose),
+            BatchOp::Delete { domain } => remove_domain(content, domain, None, verbose)?
+                .ok_or_else(|| anyhow::anyhow!("域名 '{}' 不存在，无需删除", domain)),
+            BatchOp::Disable { domain, reason } => disable_domain_lines(content, domain, reason.as_deref(), verbose),
+            BatchOp::Enable { domain } => enable_domain_lines(content, domain, verbose),
+            BatchOp::Hunk(hunk) => hunk.apply(content, verbose),
+        }
+    }
+}
+
+impl fmt::Display for BatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchOp::Create { domain, ip } => write!(f, "create {} -> {}", domain, ip),
+            BatchOp::Update { domain, ip } => write!(f, "update {} -> {}", domain, ip),
+            BatchOp::Delete { domain } => write!(f, "delete {}", domain),
+            BatchOp::Disable { domain, reason: Some(reason) } => write!(f, "disable {} ({})", domain, reason),
+            BatchOp::Disable { domain, reason: None } => write!(f, "disable {}", domain),
+            BatchOp::Enable { domain } => write!(f, "enable {}", domain),
+            BatchOp::Hunk(hunk) => write!(
+                f,
+                "@@ -{},{} +{},{} @@",
+                hunk.old_start,
+                hunk.old_lines.len(),
+                hunk.new_start,
+                hunk.new_lines.len()
+            ),
+        }
+    }
+}
+
+/// apply 的执行选项
+struct ApplyOptions {
+    /// 某条操作失败时的处理方式
+    on_error: OnError,
+    /// 只预览变更，不写入文件
+    dry_run: bool,
+    /// 预览变更时是否使用颜色
+    color: bool,
+}
+
+/// 在内存中依次执行操作，任一操作失败即返回错误，不产生部分结果
+fn apply_ops(content: &str, ops: &[BatchOp], comments: &Comments, verbose: bool) -> Result<String> {
+    ops.iter().try_fold(content.to_string(), |current, op| {
+        op.apply(&current, comments, verbose)
+            .with_context(|| format!("操作失败: {}", op))
+    })
+}
+
+/// 按批量文件执行操作：所有操作在内存中完成，最后只写入一次
+fn apply_batch(
+    batch_file: &Path,
+    store: &dyn HostsStore,
+    options: &ApplyOptions,
+    backup_ops: &[Operation],
+    comments: &Comments,
+    verbose: bool,
+) -> Result<()> {
+    let ApplyOptions { on_error, dry_run, color } = *options;
+    let batch = fs::read_to_string(batch_file)
+        .with_context(|| format!("无法读取批量文件: {}", batch_file.display()))?;
+    
+    // 检查文件
+    store.check()?;
+    
+    // 读取文件内容
+    let content = store.read()?;
+    
+    // 批量文件与 unified diff 共用同一套执行流程
+    let ops = if is_unified_diff(&batch) {
+        if verbose {
+            eprintln!("[verbose] 识别为 unified diff: {}", batch_file.display());
+        }
+        parse_unified_diff(&batch)?
+    } else {
+        batch
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| (i, BatchOp::parse(line)))
+            .collect()
+    };
+    
+    let mut new_content = content.clone();
+    let mut applied = Vec::new();
+    let mut failed = 0;
+    if on_error == OnError::Rollback {
+        // 先校验全部操作，再整体在内存中执行，任何一条失败都不写入
+        let mut valid = Vec::with_capacity(ops.len());
+        for (line_num, op) in ops {
+            match op {
+                Ok(op) => valid.push(op),
+                Err(e) => {
+                    eprintln!("  ❌ 第 {} 行: {}", line_num + 1, e);
+                    anyhow::bail!("第 {} 行校验失败，已放弃所有操作，文件未修改", line_num + 1);
+                }
+            }
+        }
+        new_content = apply_ops(&content, &valid, comments, verbose).map_err(|e| {
+            eprintln!("  ❌ {:#}", e);
+            anyhow::anyhow!("已放弃所有操作，文件未修改")
+        })?;
+        for op in &valid {
+            eprintln!("  ✅ {}", op);
+        }
+        applied = valid.iter().map(BatchOp::operation).collect();
+    } else {
+        for (line_num, op) in ops {
+            let result = op.and_then(|op| {
+                let updated = op.apply(&new_content, comments, verbose)?;
+                Ok((op, updated))
+            });
+            
+            match result {
+                Ok((op, updated)) => {
+                    eprintln!("  ✅ 第 {} 行: {}", line_num + 1, op);
+                    new_content = updated;
+                    applied.push(op.operation());
+                }
+                Err(e) => {
+                    eprintln!("  ❌ 第 {} 行: {}", line_num + 1, e);
+                    failed += 1;
+                    match on_error {
+                        OnError::Skip => continue,
+                        OnError::Stop => break,
+                        OnError::Rollback => unreachable!("回滚模式已整体执行"),
+                    }
+                }
+            }
+        }
+    }
+    
+    if dry_run {
+        eprintln!("🔍 预览变更（未写入文件）:");
+        for change in diff_lines(&content, &new_content) {
+            eprintln!("  {}", colorize_diff_line(&change, color));
+        }
+    } else if !applied.is_empty() {
+        // 写入前备份
+        if applied.iter().any(|op| backup_ops.contains(op)) {
+            store.backup()?;
+        }
+        
+        // 写入文件
+        store.write(&new_content)?;
+    }
+    
+    if failed > 0 && on_error == OnError::Stop {
+        anyhow::bail!("批量操作中途失败，已执行 {} 条操作", applied.len());
+    }
+    if failed > 0 && on_error == OnError::Skip {
+        anyhow::bail!("已执行 {} 条操作，跳过失败的 {} 条", applied.len(), failed);
+    }
+    
+    eprintln!("✅ 已执行 {} 条操作，失败 {} 条", applied.len(), failed);
+    Ok(())
+}
+
+/// unified diff 中的一个 hunk
+struct PatchHunk {
+    /// 原文件中的起始行号
+    old_start: usize,
+    /// 新文件中的起始行号
+    new_start: usize,
+    /// 上下文行与删除行
+    old_lines: Vec<String>,
+    /// 上下文行与新增行
+    new_lines: Vec<String>,
+}
+
+impl PatchHunk {
+    /// 在内容中定位 hunk 的上下文并替换；优先选择离期望行号最近的位置
+    fn apply(&self, content: &str, verbose: bool) -> Result<String> {
+        // 保留每行原有的换行符，未改动的行逐字节不变；新增的行沿用文件的换行符
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+        let body = |line: &str| line.trim_end_matches(['\r', '\n']).to_string();
+        
+        // 按顺序应用时，前面的 hunk 已经生效，新文件中的行号即为当前位置
+        let hint = if self.new_lines.is_empty() {
+            self.new_start
+        } else {
+            self.new_start.saturating_sub(1)
+        };
+        
+        let old_len = self.old_lines.len();
+        let position = (0..=lines.len().saturating_sub(old_len))
+            .filter(|&i| {
+                i + old_len <= lines.len()
+                    && lines[i..i + old_len].iter().zip(&self.old_lines).all(|(a, b)| body(a) == *b)
+            })
+            .min_by_key(|&i| i.abs_diff(hint))
+            .ok_or_else(|| anyhow::anyhow!("无法在文件中找到 hunk 的上下文（原第 {} 行）", self.old_start))?;
+        
+        if verbose {
+            eprintln!("[verbose] 在第 {} 行应用 hunk", position + 1);
+        }
+        
+        let mut result = String::with_capacity(content.len());
+        result.extend(lines[..position].iter().copied());
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push_str(newline);
+        }
+        for line in &self.new_lines {
+            result.push_str(line);
+            result.push_str(newline);
+        }
+        result.extend(lines[position + old_len..].iter().copied());
+        
+        // hunk 改到了文件末尾时，保持原文件末尾没有换行的状态
+        let at_end = position + old_len == lines.len();
+        if at_end && !content.is_empty() && !content.ends_with('\n') && result.ends_with(newline) {
+            result.truncate(result.len() - newline.len());
+        }
+        Ok(result)
+    }
+}
+
+/// 判断文本是否为 unified diff
+fn is_unified_diff(text: &str) -> bool {
+    text.lines().any(|line| line.starts_with("@@ -"))
+}
+
+/// 解析 unified diff，返回每个 hunk 及其所在行号
+fn parse_unified_diff(text: &str) -> Result<Vec<(usize, Result<BatchOp>)>> {
+    static HEADER_REGEX: OnceLock<Regex> = OnceLock::new();
+    let header_regex = HEADER_REGEX.get_or_init(|| {
+        Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@").expect("hunk 头正则无效")
+    });
+    let lines: Vec<&str> = text.lines().collect();
+    let mut hunks = Vec::new();
+    
+    let mut i = 0;
+    while i < lines.len() {
+        let Some(caps) = header_regex.captures(lines[i]) else {
+            i += 1;
+            continue;
+        };
+        
+        let header_line = i;
+        let number = |idx: usize| caps.get(idx).map_or(1, |m| m.as_str().parse().unwrap_or(0));
+        let (old_start, old_count, new_start, new_count) = (number(1), number(2), number(3), number(4));
+        
+        let mut hunk = PatchHunk {
+            old_start,
+            new_start,
+            old_lines: Vec::new(),
+            new_lines: Vec::new(),
+        };
+        
+        i += 1;
+        while i < lines.len() && (hunk.old_lines.len() < old_count || hunk.new_lines.len() < new_count) {
+            let line = lines[i];
+            if let Some(rest) = line.strip_prefix('-') {
+                hunk.old_lines.push(rest.to_string());
+            } else if let Some(rest) = line.strip_prefix('+') {
+                hunk.new_lines.push(rest.to_string());
+            } else if line.starts_with('\\') {
+                // "\ No newline at end of file"
+            } else {
+                // 上下文行，部分工具会去掉空行前的空格
+                let rest = line.strip_prefix(' ').unwrap_or(line);
+                hunk.old_lines.push(rest.to_string());
+                hunk.new_lines.push(rest.to_string());
+            }
+            i += 1;
+        }
+        
+        let result = if hunk.old_lines.len() == old_count && hunk.new_lines.len() == new_count {
+            Ok(BatchOp::Hunk(hunk))
+        } else {
+            Err(anyhow::anyhow!("hunk 行数与头部不符: {}", lines[header_line]))
+        };
+        hunks.push((header_line, result));
+    }
+    
+    Ok(hunks)
+}
+
+/// 启用颜色时用 ANSI 控制符包裹文本
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// 为 diff 行添加颜色：删除为红色，新增为绿色，其余为灰色
+fn colorize_diff_line(line: &str, color: bool) -> String {
+    if !color {
+        return line.to_string();
+    }
+    
+    let code = if line.starts_with('-') {
+        "31"
+    } else if line.starts_with('+') {
+        "32"
+    } else {
+        "90"
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, line)
+}
+
+/// 由实际计算出的新内容推导的逐行变更，用于 --plan
+struct ChangeReport<'a> {
+    changes: Vec<LineChange<'a>>,
+}
+
+/// 单行变更，行号从 1 开始
+enum LineChange<'a> {
+    Changed { line: usize, from: &'a str, to: &'a str },
+    Removed { line: usize, text: &'a str },
+    Added { line: usize, text: &'a str },
+}
+
+impl<'a> ChangeReport<'a> {
+    /// 逐行比较两段内容，紧邻的删除和插入合并为一处修改
+    fn between(old: &'a str, new: &'a str) -> Self {
+        let old_lines: Vec<&str> = old.lines().collect();
+        let new_lines: Vec<&str> = new.lines().collect();
+        
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        for line in &new_lines {
+            *remaining.entry(line).or_insert(0) += 1;
+        }
+        
+        let mut changes = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old_lines.len() || j < new_lines.len() {
+            if i < old_lines.len() && j < new_lines.len() && old_lines[i] == new_lines[j] {
+                *remaining.get_mut(new_lines[j]).unwrap() -= 1;
+                i += 1;
+                j += 1;
+            } else if i < old_lines.len() && (j >= new_lines.len() || remaining.get(old_lines[i]).copied().unwrap_or(0) == 0) {
+                changes.push(LineChange::Removed { line: i + 1, text: old_lines[i] });
+                i += 1;
+            } else {
+                *remaining.get_mut(new_lines[j]).unwrap() -= 1;
+                match changes.last() {
+                    Some(&LineChange::Removed { line, text }) if line == i => {
+                        changes.pop();
+                        changes.push(LineChange::Changed { line, from: text, to: new_lines[j] });
+                    }
+                    _ => changes.push(LineChange::Added { line: j + 1, text: new_lines[j] }),
+                }
+                j += 1;
+            }
+        }
+        
+        ChangeReport { changes }
+    }
+    
+    /// 被修改或删除的映射行原来的 IP，按出现顺序去重
+    fn previous_ips(&self) -> Vec<&'a str> {
+        let mut ips = Vec::new();
+        for change in &self.changes {
+            let old = match change {
+                LineChange::Changed { from, .. } => from,
+                LineChange::Removed { text, .. } => text,
+                LineChange::Added { .. } => continue,
+            };
+            if let Some(ip) = old.split_whitespace().next().filter(|ip| ip.parse::<IpAddr>().is_ok()) {
+                if !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+        ips
+    }
+    
+    fn print(&self) {
+        if self.changes.is_empty() {
+            eprintln!("📋 变更计划（未写入文件）: 无需修改");
+            return;
+        }
+        eprintln!("📋 变更计划（未写入文件，共 {} 处）:", self.changes.len());
+        for change in &self.changes {
+            eprintln!("  {}", change);
+        }
+    }
+}
+
+impl fmt::Display for LineChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineChange::Changed { line, from, to } => write!(f, "将第 {} 行从 `{}` 改为 `{}`", line, from, to),
+            LineChange::Removed { line, text } => write!(f, "将删除第 {} 行 `{}`", line, text),
+            LineChange::Added { line, text } => write!(f, "将在第 {} 行插入 `{}`", line, text),
+        }
+    }
+}
+
+/// 逐行比较两段内容，返回以 "-"/"+" 标记的变更行
+fn diff_lines(old: &str, new: &str) -> Vec<String> {
+    ChangeReport::between(old, new)
+        .changes
+        .iter()
+        .flat_map(|change| match change {
+            LineChange::Changed { from, to, .. } => vec![format!("- {}", from), format!("+ {}", to)],
+            LineChange::Removed { text, .. } => vec![format!("- {}", text)],
+            LineChange::Added { text, .. } => vec![format!("+ {}", text)],
+        })
+        .collect()
+}
+
+/// 逐行读取并以 JSON Lines 输出映射，内存占用与文件大小无关
+fn stream_entries_jsonl(stores: &[&dyn HostsStore], options: &ListOptions, out: &mut Output) -> Result<usize> {
+    let ListOptions { offset, limit, .. } = *options;
+    let mut index = 0;
+    let mut shown = 0;
+    for store in stores {
+        store.check()?;
+        let source = store.name();
+        for (line_num, line) in store.reader()?.lines().enumerate() {
+            let line = line.context("读取 hosts 文件失败")?;
+            let Some(entry) = HostEntry::parse(&line, &source, line_num + 1) else {
+                continue;
+            };
+            if !options.matches(&entry) {
+                continue;
+            }
+            
+            index += 1;
+            if index <= offset {
+                continue;
+            }
+            if limit > 0 && shown >= limit {
+                return Ok(shown);
+            }
+            
+            writeln!(out, "{}", serde_json::to_string(&entry)?)?;
+            shown += 1;
+        }
+    }
+    
+    Ok(shown)
+}
+
+/// hosts 文件中的一条域名映射
+#[derive(serde::Serialize)]
+struct HostEntry {
+    /// 来源文件
+    source: String,
+    /// 所在行号（从 1 开始）
+    line: usize,
+    ip: String,
+    domains: Vec<String>,
+    /// 行内注释（不含 #）
+    comment: Option<String>,
+}
+
+/// 是否为屏蔽映射：指向 0.0.0.0 或 127.0.0.1，且不是 localhost 自身的映射
+fn is_blocking_entry(entry: &HostEntry) -> bool {
+    matches!(entry.ip.as_str(), "0.0.0.0" | "127.0.0.1") && !entry.domains.iter().any(|name| name == "localhost")
+}
+
+/// 只读地查找域名所在的第一条映射；比较时忽略大小写和末尾的点，国际化域名按 Punycode 形式比较。
+/// 返回的 source 为空，由调用方填写
+fn find_domain(content: &str, domain: &str) -> Option<HostEntry> {
+    let normalize = |name: &str| name.trim_end_matches('.').to_ascii_lowercase();
+    let wanted = ascii_domain(domain).map_or_else(|_| normalize(domain), |domain| normalize(&domain));
+    content.lines().enumerate().find_map(|(line_idx, line)| {
+        HostEntry::parse(line, "", line_idx + 1)
+            .filter(|entry| entry.domains.iter().any(|name| normalize(name) == wanted))
+    })
+}
+
+impl HostEntry {
+    /// 解析单行映射，非映射行返回 None
+    fn parse(line: &str, source: &str, line_num: usize) -> Option<Self> {
+        let (ip, domains) = parse_mapping(line)?;
+        Some(HostEntry {
+            source: source.to_string(),
+            line: line_num,
+            ip: ip.to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            comment: line
+                .split_once('#')
+                .map(|(_, c)| c.trim().to_string())
+                .filter(|c| !c.is_empty()),
+        })
+    }
+}
+
+/// 解析后的 hosts 文件
+struct HostsFile {
+    entries: Vec<HostEntry>,
+}
+
+impl HostsFile {
+    /// 从文件内容中解析所有映射行，source 记录内容来源
+    fn parse(content: &str, source: &str) -> Self {
+        let entries = content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| HostEntry::parse(line, source, i + 1))
+            .collect();
+        
+        HostsFile { entries }
+    }
+    
+    /// 读取并合并多个存储中的映射
+    fn load(stores: &[&dyn HostsStore]) -> Result<Self> {
+        let mut hosts = HostsFile { entries: Vec::new() };
+        for store in stores {
+            store.check()?;
+            let content = store.read()?;
+            hosts.entries.extend(HostsFile::parse(&content, &store.name()).entries);
+        }
+        Ok(hosts)
+    }
+    
+    /// 分页获取映射，limit 为 0 时返回 offset 之后的全部映射
+    fn entries_paged(&self, offset: usize, limit: usize) -> &[HostEntry] {
+        let start = offset.min(self.entries.len());
+        let end = if limit == 0 {
+            self.entries.len()
+        } else {
+            start.saturating_add(limit).min(self.entries.len())
+        };
+        &self.entries[start..end]
+    }
+}
+
+/// 域名未找到时，在终端中提示编辑距离最近的已有域名；标准错误不是终端时不提示
+fn suggest_similar_domain(content: &str, domain: &str) {
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
+    if let Some(candidate) = similar_domain(content, domain) {
+        eprintln!("💡 域名未找到，您是否想找 '{}'?", candidate);
+    }
+}
+
+/// 编辑距离最近且足够接近的已有域名，距离相同时取先出现的
+fn similar_domain(content: &str, domain: &str) -> Option<String> {
+    let hosts = HostsFile::parse(content, "");
+    let max_distance = (domain.chars().count() / 4).clamp(1, 3);
+    hosts
+        .entries
+        .into_iter()
+        .flat_map(|entry| entry.domains)
+        .map(|candidate| (edit_distance(domain, &candidate), candidate))
+        .filter(|(distance, _)| *distance > 0 && *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// 计算两个字符串的 Levenshtein 编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    
+    prev[b.len()]
+}
+
+/// 构建匹配完整域名的正则（internal 不会匹配 ns1.internal 或 internal-api）；域名会被转义，编译失败（如超出大小限制）时返回可读的错误
+fn domain_regex(domain: &str) -> Result<Regex> {
+    Regex::new(&format!(r"(?:^|[^\w.-]){}(?:$|[^\w.-])", regex::escape(domain)))
+        .map_err(|e| anyhow::anyhow!("无法匹配域名 '{}': {}", domain, regex_error_text(&e)))
+}
+
+/// 提取 regex 错误中对用户有用的部分，不输出带插入符号的多行格式
+fn regex_error_text(e: &regex::Error) -> String {
+    match e {
+        regex::Error::CompiledTooBig(limit) => format!("编译后的规则超出大小限制（{} 字节）", limit),
+        regex::Error::Syntax(text) => text.lines().last().unwrap_or(text).trim().trim_start_matches("error: ").to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// 列出文本上包含域名但未被识别为映射的行，并说明原因
+fn explain_no_match(content: &str, domain: &str) {
+    let ip_regex = ip_regex();
+    let Ok(domain_regex) = domain_regex(domain) else {
+        return;
+    };
+    
+    let candidates: Vec<(usize, &str)> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.contains(domain))
+        .collect();
+    
+    if candidates.is_empty() {
+        eprintln!("💡 文件中没有任何行包含 '{}'", domain);
+        return;
+    }
+    
+    eprintln!("💡 以下行包含 '{}'，但未被识别为映射:", domain);
+    for (line_num, line) in candidates {
+        let trimmed = line.trim_start();
+        let reason = if let Some(rest) = trimmed.strip_prefix('#') {
+            if ip_regex.is_match(rest.trim_start()) {
+                "被注释掉的映射"
+            } else {
+                "位于注释中"
+            }
+        } else if !ip_regex.is_match(line) {
+            if ip_regex.is_match(trimmed) {
+                "行首存在空白字符"
+            } else {
+                "行首不是 IP 地址"
+            }
+        } else if !domain_regex.is_match(line) {
+            "只是部分匹配，不是完整的域名"
+        } else {
+            "原因未知"
+        };
+        eprintln!("  line {}: {}", line_num + 1, reason);
+        eprintln!("    {}", line);
+    }
+}
+
+/// 识别映射行开头 IP 地址的正则，只编译一次；IPv6 地址可带 %接口 后缀，如 fe80::1%lo0
+fn ip_regex() -> &'static IpRegex {
+    static IP_REGEX: OnceLock<IpRegex> = OnceLock::new();
+    IP_REGEX.get_or_init(|| {
+        IpRegex(
+            Regex::new(r"^(([0-9]+\.){3}[0-9]+|[0-9A-Fa-f]*:[0-9A-Fa-f:.]*(%[0-9A-Za-z]+)?)[[:space:]]+")
+                .expect("IP 正则无效"),
+        )
+    })
+}
+
+/// 行首 IP 的匹配器：正则只做快速筛选，`cafe: x` 这类形似 IPv6 的内容再经 IpAddr 解析排除
+struct IpRegex(Regex);
+
+impl IpRegex {
+    /// 行首是合法的 IP 地址且其后跟着空白
+    fn is_match(&self, line: &str) -> bool {
+        self.0.find(line).is_some_and(|found| {
+            let ip = found.as_str().trim_end();
+            let address = ip.split_once('%').map_or(ip, |(address, _)| address);
+            address.parse::<IpAddr>().is_ok()
+        })
+    }
+}
+
+/// 识别 hostm 默认注释（created/updated by [<user> via ]hostm）的正则，只编译一次
+fn managed_regex() -> &'static Regex {
+    static MANAGED_REGEX: OnceLock<Regex> = OnceLock::new();
+    MANAGED_REGEX.get_or_init(|| Regex::new(r"# (created|updated) by (\S+ via )?hostm\b").expect("hostm 注释正则无效"))
+}
+
+/// 提取 hostm 过期时间注释的正则，只编译一次
+fn expires_regex() -> &'static Regex {
+    static EXPIRES_REGEX: OnceLock<Regex> = OnceLock::new();
+    EXPIRES_REGEX.get_or_init(|| Regex::new(r"# hostm expires (\S+)").expect("过期时间正则无效"))
+}
+
+/// 将映射行解析为 IP 和域名列表，忽略行内注释
+fn parse_mapping(line: &str) -> Option<(&str, Vec<&str>)> {
+    if !ip_regex().is_match(line) {
+        return None;
+    }
+    
+    let data = line.split('#').next().unwrap_or("");
+    let mut tokens = data.split_whitespace();
+    let ip = tokens.next()?;
+    let domains: Vec<&str> = tokens.collect();
+    if domains.is_empty() {
+        return None;
+    }
+    
+    Some((ip, domains))
+}
+
+/// hosts 文件的存储后端，将命令逻辑与实际的读写方式解耦
+trait HostsStore {
+    /// 用于提示信息的名称
+    fn name(&self) -> String;
+    /// 检查 hosts 文件是否存在且可用
+    fn check(&self) -> Result<()>;
+    /// 读取完整内容
+    fn read(&self) -> Result<String>;
+    /// 按行读取内容，用于只读的流式扫描
+    fn reader(&self) -> Result<Box<dyn BufRead>>;
+    /// 写入完整内容
+    fn write(&self, content: &str) -> Result<()>;
+    /// 备份当前内容，返回备份位置
+    fn backup(&self) -> Result<String>;
+    /// 当前内容的 SHA-256，按原始字节计算，与 sha256sum 对同一文件的结果一致
+    fn checksum(&self) -> Result<String> {
+        Ok(sha256_hex(&self.read()?))
+    }
+    /// 是否存在上次中断、尚未完成的写入
+    fn has_pending_write(&self) -> bool {
+        false
+    }
+    /// 处理未完成的写入：complete 为真时按日志完成写入，否则丢弃日志；返回是否存在未完成的写入
+    fn recover(&self, _complete: bool) -> Result<bool> {
+        Ok(false)
+    }
+    /// 读取 hostm lock 设置的锁定信息，未锁定时返回 None
+    fn lock_info(&self) -> Result<Option<LockInfo>> {
+        Ok(None)
+    }
+    /// 设置锁定信息，传入 None 时解除锁定
+    fn set_lock(&self, _lock: Option<&LockInfo>) -> Result<()> {
+        anyhow::bail!("{} 不支持锁定", self.name())
+    }
+}
+
+/// hostm lock 记录的锁定信息，以 `键=值` 的形式逐行保存
+struct LockInfo {
+    locked_at: DateTime<Local>,
+    user: String,
+    reason: Option<String>,
+}
+
+impl LockInfo {
+    fn parse(content: &str) -> Result<Self> {
+        let mut locked_at = None;
+        let mut user = String::new();
+        let mut reason = None;
+        for line in content.lines() {
+            match line.split_once('=') {
+                Some(("locked_at", value)) => {
+                    locked_at = Some(
+                        DateTime::parse_from_rfc3339(value.trim())
+                            .with_context(|| format!("无效的锁定时间: {}", value))?
+                            .with_timezone(&Local),
+                    );
+                }
+                Some(("user", value)) => user = value.trim().to_string(),
+                Some(("reason", value)) => reason = Some(value.trim().to_string()).filter(|r| !r.is_empty()),
+                _ => {}
+            }
+        }
+        Ok(LockInfo {
+            locked_at: locked_at.context("锁定文件缺少 locked_at")?,
+            user,
+            reason,
+        })
+    }
+    
+    fn serialize(&self) -> String {
+        let mut content = format!("locked_at={}\nuser={}\n", self.locked_at.to_rfc3339(), self.user);
+        if let Some(reason) = &self.reason {
+            content.push_str(&format!("reason={}\n", reason.replace('\n', " ")));
+        }
+        content
+    }
+}
+
+impl fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.locked_at.format("%Y-%m-%d %H:%M"))?;
+        if self.user.is_empty() {
+            write!(f, "锁定")?;
+        } else {
+            write!(f, "由 {} 锁定", self.user)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, "，原因: {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// 根据 --hosts-file 的形式选择存储后端，ssh:// 开头时使用远程主机
+fn open_store(
+    path: &Path,
+    check_changes: bool,
+    write_options: WriteOptions,
+    expected_checksum: Option<&str>,
+    max_backups_size: Option<u64>,
+    verbose: bool,
+) -> Result<Box<dyn HostsStore>> {
+    match path.to_str().and_then(|p| p.strip_prefix("ssh://")) {
+        #[cfg(feature = "ssh")]
+        Some(target) => Ok(Box::new(SshStore::connect(target, write_options, expected_checksum, verbose)?)),
+        #[cfg(not(feature = "ssh"))]
+        Some(_) => anyhow::bail!("不支持 ssh:// 路径，请使用 --features ssh 重新编译 hostm"),
+        None => {
+            let mut store = FileStore::new(path, check_changes, write_options, expected_checksum, verbose);
+            store.max_backups_size = max_backups_size;
+            Ok(Box::new(store))
+        }
+    }
+}
+
+/// 从原存储读取、将结果写入另一个文件（--output-file）或标准输出（--output-only）的存储后端，原存储保持不变
+struct RedirectStore {
+    source: Box<dyn HostsStore>,
+    /// 结果写入的文件，None 表示写入标准输出
+    output: Option<PathBuf>,
+    write_options: WriteOptions,
+    verbose: bool,
+}
+
+impl HostsStore for RedirectStore {
+    fn name(&self) -> String {
+        self.source.name()
+    }
+    
+    fn check(&self) -> Result<()> {
+        self.source.check()
+    }
+    
+    fn read(&self) -> Result<String> {
+        self.source.read()
+    }
+    
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        self.source.reader()
+    }
+    
+    fn write(&self, content: &str) -> Result<()> {
+        let prepared = self.write_options.prepare(content);
+        match &self.output {
+            Some(output) => {
+                write_hosts_file(output, &prepared, &output.display().to_string(), self.verbose)?;
+                eprintln!("📋 结果已写入: {}，{} 未修改", output.display(), self.source.name());
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(prepared.as_bytes())?;
+                stdout.flush()?;
+                eprintln!("📋 结果已输出到标准输出，{} 未修改", self.source.name());
+            }
+        }
+        Ok(())
+    }
+    
+    fn backup(&self) -> Result<String> {
+        self.source.backup()
+    }
+    
+    fn checksum(&self) -> Result<String> {
+        self.source.checksum()
+    }
+    
+    fn has_pending_write(&self) -> bool {
+        self.source.has_pending_write()
+    }
+}
+
+/// 写入成功后将每处变更追加到审计日志（--audit-log）；日志写入失败只给出警告，不影响命令结果
+struct AuditLog {
+    inner: Box<dyn HostsStore>,
+    path: PathBuf,
+    /// 最近一次读取的内容，用于与写入的内容比较
+    last_read: RefCell<Option<String>>,
+}
+
+impl AuditLog {
+    /// 每处变更一行：时间、用户、文件、操作、域名、原 IP、新 IP，缺少的字段记为 -
+    fn entries(&self, old: &str, new: &str) -> Vec<String> {
+        // 通过 sudo 运行时记录实际操作的用户
+        let user = std::env::var("SUDO_USER").unwrap_or_else(|_| default_user());
+        let user = if user.is_empty() { "-".to_string() } else { user };
+        let timestamp = Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+        let mapping = |line: &str| parse_mapping(line).map(|(ip, domains)| (ip.to_string(), domains.join(",")));
+        
+        ChangeReport::between(old, new)
+            .changes
+            .iter()
+            .map(|change| {
+                let (op, before, after) = match change {
+                    LineChange::Added { text, .. } => ("add", None, mapping(text)),
+                    LineChange::Removed { text, .. } => ("remove", mapping(text), None),
+                    LineChange::Changed { from, to, .. } => ("change", mapping(from), mapping(to)),
+                };
+                let domain = after.as_ref().or(before.as_ref()).map_or("-", |(_, domains)| domains.as_str());
+                format!(
+                    "{} user={} file={} op={} domain={} old={} new={}",
+                    timestamp,
+                    user,
+                    self.inner.name(),
+                    op,
+                    domain,
+                    before.as_ref().map_or("-", |(ip, _)| ip.as_str()),
+                    after.as_ref().map_or("-", |(ip, _)| ip.as_str())
+                )
+            })
+            .collect()
+    }
+    
+    fn append(&self, entries: &[String]) -> std::io::Result<()> {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        for entry in entries {
+            writeln!(file, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl HostsStore for AuditLog {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    
+    fn check(&self) -> Result<()> {
+        self.inner.check()
+    }
+    
+    fn read(&self) -> Result<String> {
+        let content = self.inner.read()?;
+        *self.last_read.borrow_mut() = Some(content.clone());
+        Ok(content)
+    }
+    
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        self.inner.reader()
+    }
+    
+    fn write(&self, content: &str) -> Result<()> {
+        self.inner.write(content)?;
+        let old = self.last_read.borrow().clone().unwrap_or_default();
+        let entries = self.entries(&old, content);
+        if let Err(e) = self.append(&entries) {
+            eprintln!("⚠️  无法写入审计日志 {}: {}", self.path.display(), e);
+        }
+        *self.last_read.borrow_mut() = Some(content.to_string());
+        Ok(())
+    }
+    
+    fn backup(&self) -> Result<String> {
+        self.inner.backup()
+    }
+    
+    fn checksum(&self) -> Result<String> {
+        self.inner.checksum()
+    }
+    
+    fn has_pending_write(&self) -> bool {
+        self.inner.has_pending_write()
+    }
+    
+    fn recover(&self, complete: bool) -> Result<bool> {
+        self.inner.recover(complete)
+    }
+    
+    fn lock_info(&self) -> Result<Option<LockInfo>> {
+        self.inner.lock_info()
+    }
+    
+    fn set_lock(&self, lock: Option<&LockInfo>) -> Result<()> {
+        self.inner.set_lock(lock)
+    }
+}
+
+/// 写入前检查是否修改或删除了 localhost 映射，是则拒绝写入（--allow-localhost-edit 可关闭）
+struct LocalhostGuard {
+    inner: Box<dyn HostsStore>,
+    /// 最近一次读取的内容，用于与待写入的内容比较
+    last_read: RefCell<Option<String>>,
+}
+
+impl HostsStore for LocalhostGuard {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    
+    fn check(&self) -> Result<()> {
+        self.inner.check()
+    }
+    
+    fn read(&self) -> Result<String> {
+        let content = self.inner.read()?;
+        *self.last_read.borrow_mut() = Some(content.clone());
+        Ok(content)
+    }
+    
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        self.inner.reader()
+    }
+    
+    fn write(&self, content: &str) -> Result<()> {
+        if let Some(original) = self.last_read.borrow().as_deref() {
+            // 只要写入后同一 IP 仍有 localhost 映射即可，调整注释或空白不受限制
+            let lost = original.lines().enumerate().find(|(_, line)| {
+                localhost_ip(line).is_some_and(|ip| !content.lines().any(|new| localhost_ip(new) == Some(ip)))
+            });
+            if let Some((line_idx, text)) = lost {
+                let line = line_idx + 1;
+                eprintln!("⚠️  修改或删除 localhost 映射可能导致本机服务无法解析 localhost，默认不允许此类修改");
+                anyhow::bail!(
+                    "拒绝修改第 {} 行的 localhost 映射: {}，如确需修改请加上 --allow-localhost-edit",
+                    line,
+                    text
+                );
+            }
+        }
+        self.inner.write(content)
+    }
+    
+    fn backup(&self) -> Result<String> {
+        self.inner.backup()
+    }
+    
+    fn checksum(&self) -> Result<String> {
+        self.inner.checksum()
+    }
+    
+    fn has_pending_write(&self) -> bool {
+        self.inner.has_pending_write()
+    }
+    
+    fn recover(&self, complete: bool) -> Result<bool> {
+        self.inner.recover(complete)
+    }
+    
+    fn lock_info(&self) -> Result<Option<LockInfo>> {
+        self.inner.lock_info()
+    }
+    
+    fn set_lock(&self, lock: Option<&LockInfo>) -> Result<()> {
+        self.inner.set_lock(lock)
+    }
+}
+
+/// 行是 127.0.0.1 或 ::1 指向 localhost 的系统映射时，返回其 IP
+fn localhost_ip(line: &str) -> Option<&str> {
+    let data = line.split('#').next().unwrap_or("");
+    let mut tokens = data.split_whitespace();
+    let ip = tokens.next().filter(|ip| matches!(*ip, "127.0.0.1" | "::1"))?;
+    tokens.any(|name| name == "localhost").then_some(ip)
+}
+
+/// 基于本地文件系统的存储后端
+struct FileStore {
+    path: PathBuf,
+    /// 写入前检查文件是否在读取后被修改
+    check_changes: bool,
+    /// 最近一次读取时内容的 SHA-256
+    read_checksum: RefCell<Option<String>>,
+    /// 写入前要求文件当前内容具有的 SHA-256
+    expected_checksum: Option<String>,
+    /// 写入前对内容做的整理
+    write_options: WriteOptions,
+    /// 备份总大小的上限（--max-backups-size）
+    max_backups_size: Option<u64>,
+    verbose: bool,
+}
+
+impl FileStore {
+    fn new(path: &Path, check_changes: bool, write_options: WriteOptions, expected_checksum: Option<&str>, verbose: bool) -> Self {
+        FileStore {
+            path: path.to_path_buf(),
+            check_changes,
+            read_checksum: RefCell::new(None),
+            expected_checksum: expected_checksum.map(|c| c.to_ascii_lowercase()),
+            write_options,
+            max_backups_size: None,
+            verbose,
+        }
+    }
+    
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// 写入前记录目标内容的预写日志（`<hosts>.hostm.wal`）
+    fn wal_path(&self) -> PathBuf {
+        let mut wal_path = self.path.clone().into_os_string();
+        wal_path.push(".hostm.wal");
+        PathBuf::from(wal_path)
+    }
+    
+    /// hostm lock 写入的锁定文件（`<hosts>.hostm.lock`）
+    fn lock_path(&self) -> PathBuf {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".hostm.lock");
+        PathBuf::from(lock_path)
+    }
+    
+    /// 备份总大小超过 limit 时从最旧的 `<hosts>.<时间戳>.bak` 开始删除，latest 不会被删除
+    fn prune_backups(&self, limit: u64, latest: &Path) -> Result<()> {
+        let dir = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let Some(file_name) = self.path.file_name().and_then(|name| name.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", file_name);
+        
+        let mut backups: Vec<(PathBuf, u64)> = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("无法读取备份目录: {}", dir.display()))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(timestamp) = name.to_str().and_then(|name| name.strip_prefix(&prefix)?.strip_suffix(".bak")) else {
+                continue;
+            };
+            if !timestamp.is_empty() && timestamp.bytes().all(|b| b.is_ascii_digit()) {
+                backups.push((entry.path(), entry.metadata()?.len()));
+            }
+        }
+        // 时间戳定长，按文件名排序即按时间排序
+        backups.sort();
+        
+        let mut total: u64 = backups.iter().map(|(_, size)| size).sum();
+        let mut removed = 0;
+        for (path, size) in &backups {
+            if total <= limit {
+                break;
+            }
+            if path == latest {
+                continue;
+            }
+            if self.verbose {
+                eprintln!("[verbose] 删除旧备份: {}（{} 字节）", path.display(), size);
+            }
+            fs::remove_file(path).with_context(|| format!("无法删除旧备份: {}", path.display()))?;
+            total -= size;
+            removed += 1;
+        }
+        if removed > 0 {
+            eprintln!("📋 已删除 {} 个旧备份，现有备份共 {} 字节", removed, total);
+        }
+        Ok(())
+    }
+}
+
+impl HostsStore for FileStore {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+    
+    fn check(&self) -> Result<()> {
+        check_hosts_file(self.path(), self.verbose)
+    }
+    
+    fn read(&self) -> Result<String> {
+        let (content, checksum) = read_hosts_with_checksum(self.path())?;
+        if self.verbose {
+            eprintln!("[verbose] 读取内容的 SHA-256: {}", checksum);
+        }
+        *self.read_checksum.borrow_mut() = Some(checksum);
+        // 校验和按原始内容计算，之后再去掉 BOM
+        match content.strip_prefix(UTF8_BOM) {
+            Some(stripped) => {
+                if self.verbose {
+                    eprintln!("[verbose] ⚠️  {} 以 UTF-8 BOM 开头，已忽略", self.path.display());
+                }
+                Ok(stripped.to_string())
+            }
+            None => Ok(content),
+        }
+    }
+    
+    #[cfg(feature = "mmap")]
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        let content = mmap_read_hosts(self.path())
+            .with_context(|| format!("无法读取文件: {}", self.path.display()))?;
+        let mut reader = std::io::Cursor::new(content);
+        skip_bom(&mut reader, self.path(), self.verbose)?;
+        Ok(Box::new(reader))
+    }
+    
+    #[cfg(not(feature = "mmap"))]
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        let file = fs::File::open(self.path())
+            .with_context(|| format!("无法读取文件: {}", self.path.display()))?;
+        let mut reader = std::io::BufReader::new(file);
+        skip_bom(&mut reader, self.path(), self.verbose)?;
+        Ok(Box::new(reader))
+    }
+    
+    fn write(&self, content: &str) -> Result<()> {
+        let prepared = self.write_options.prepare(content);
+        let content: &str = &prepared;
+        
+        // 写入前重新读取，避免覆盖读取之后其他进程的修改
+        let read_checksum = self.read_checksum.borrow().clone().filter(|_| self.check_changes);
+        if read_checksum.is_some() || self.expected_checksum.is_some() {
+            let (_, current) = read_hosts_with_checksum(self.path())?;
+            if let Some(expected) = &self.expected_checksum {
+                if current != *expected {
+                    anyhow::bail!(
+                        "hosts 文件的 SHA-256 与 --expected-checksum 不一致，已放弃写入: {}（当前为 {}）",
+                        self.path.display(),
+                        current
+                    );
+                }
+            }
+            if read_checksum.is_some_and(|read| read != current) {
+                anyhow::bail!("hosts 文件在读取后已被修改，已放弃写入: {}", self.path.display());
+            }
+            if self.verbose {
+                eprintln!("[verbose] hosts 文件在读取后未被修改");
+            }
+        }
+        
+        // 先将最终内容写入预写日志并落盘，写入中断时可据此完成或放弃
+        let wal_path = self.wal_path();
+        if self.verbose {
+            eprintln!("[verbose] 写入预写日志: {}", wal_path.display());
+        }
+        let mut wal = fs::File::create(&wal_path)
+            .with_context(|| format!("无法写入预写日志: {}", wal_path.display()))?;
+        wal.write_all(content.as_bytes())
+            .and_then(|_| wal.sync_all())
+            .with_context(|| format!("无法写入预写日志: {}", wal_path.display()))?;
+        
+        write_hosts_file(self.path(), content, &self.path.display().to_string(), self.verbose)?;
+        
+        fs::remove_file(&wal_path)
+            .with_context(|| format!("无法删除预写日志: {}", wal_path.display()))
+    }
+    
+    fn backup(&self) -> Result<String> {
+        let timestamp = Local::now().format("%Y%m%d%H%M%S%3f");
+        let mut backup_path = self.path.clone().into_os_string();
+        backup_path.push(format!(".{}.bak", timestamp));
+        let backup_path = PathBuf::from(backup_path);
+        
+        if self.verbose {
+            eprintln!("[verbose] 备份 hosts 文件到: {}", backup_path.display());
+        }
+        
+        fs::copy(&self.path, &backup_path)
+            .with_context(|| format!("无法备份文件到: {}", backup_path.display()))?;
+        if let Some(limit) = self.max_backups_size {
+            self.prune_backups(limit, &backup_path)?;
+        }
+        Ok(backup_path.display().to_string())
+    }
+    
+    fn checksum(&self) -> Result<String> {
+        // read() 会去掉 BOM，这里按原样读取
+        read_hosts_with_checksum(self.path()).map(|(_, checksum)| checksum)
+    }
+    
+    fn has_pending_write(&self) -> bool {
+        self.wal_path().exists()
+    }
+    
+    fn recover(&self, complete: bool) -> Result<bool> {
+        let wal_path = self.wal_path();
+        if !wal_path.exists() {
+            return Ok(false);
+        }
+        
+        if complete {
+            let content = fs::read_to_string(&wal_path)
+                .with_context(|| format!("无法读取预写日志: {}", wal_path.display()))?;
+            if self.verbose {
+                eprintln!("[verbose] 按预写日志完成写入: {}", wal_path.display());
+            }
+            write_hosts_file(self.path(), &content, &self.path.display().to_string(), self.verbose)?;
+        } else if self.verbose {
+            eprintln!("[verbose] 丢弃预写日志: {}", wal_path.display());
+        }
+        
+        fs::remove_file(&wal_path)
+            .with_context(|| format!("无法删除预写日志: {}", wal_path.display()))?;
+        Ok(true)
+    }
+    
+    fn lock_info(&self) -> Result<Option<LockInfo>> {
+        let lock_path = self.lock_path();
+        if !lock_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&lock_path)
+            .with_context(|| format!("无法读取锁定文件: {}", lock_path.display()))?;
+        LockInfo::parse(&content)
+            .with_context(|| format!("锁定文件格式错误: {}", lock_path.display()))
+            .map(Some)
+    }
+    
+    fn set_lock(&self, lock: Option<&LockInfo>) -> Result<()> {
+        let lock_path = self.lock_path();
+        match lock {
+            Some(lock) => {
+                if self.verbose {
+                    eprintln!("[verbose] 写入锁定文件: {}", lock_path.display());
+                }
+                fs::write(&lock_path, lock.serialize())
+                    .with_context(|| format!("无法写入锁定文件: {}", lock_path.display()))
+            }
+            None => {
+                if self.verbose {
+                    eprintln!("[verbose] 删除锁定文件: {}", lock_path.display());
+                }
+                fs::remove_file(&lock_path)
+                    .with_context(|| format!("无法删除锁定文件: {}", lock_path.display()))
+            }
+        }
+    }
+}
+
+/// 通过 SSH（SFTP）读写远程主机上的 hosts 文件，使用系统 ssh-agent 认证
+#[cfg(feature = "ssh")]
+struct SshStore {
+    user: String,
+    host: String,
+    path: String,
+    sftp: ssh2::Sftp,
+    /// 写入前要求文件当前内容具有的 SHA-256
+    expected_checksum: Option<String>,
+    /// 写入前对内容做的整理
+    write_options: WriteOptions,
+    verbose: bool,
+}
+
+#[cfg(feature = "ssh")]
+impl SshStore {
+    /// 连接 `user@host[:port]:/path` 或 `user@host[:port]/path` 形式的目标
+    fn connect(target: &str, write_options: WriteOptions, expected_checksum: Option<&str>, verbose: bool) -> Result<Self> {
+        let slash = target
+            .find('/')
+            .ok_or_else(|| anyhow::anyhow!("ssh 路径缺少远程文件路径: ssh://{}", target))?;
+        let (authority, path) = target.split_at(slash);
+        let authority = authority.strip_suffix(':').unwrap_or(authority);
+        
+        let (user, host_port) = match authority.split_once('@') {
+            Some((user, host_port)) => (user.to_string(), host_port),
+            None => (std::env::var("USER").context("无法确定 ssh 用户名，请在路径中指定 user@")?, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().with_context(|| format!("无效的 ssh 端口: {}", port))?),
+            None => (host_port, 22),
+        };
+        
+        if verbose {
+            eprintln!("[verbose] 连接 ssh: {}@{}:{}", user, host, port);
+        }
+        
+        let tcp = std::net::TcpStream::connect((host, port))
+            .with_context(|| format!("无法连接到 {}:{}", host, port))?;
+        let mut session = ssh2::Session::new().context("无法创建 ssh 会话")?;
+        session.set_tcp_stream(tcp);
+        session.handshake().with_context(|| format!("ssh 握手失败: {}", host))?;
+        session
+            .userauth_agent(&user)
+            .with_context(|| format!("ssh-agent 认证失败: {}@{}", user, host))?;
+        let sftp = session.sftp().context("无法打开 sftp 通道")?;
+        
+        Ok(SshStore {
+            user,
+            host: host.to_string(),
+            path: path.to_string(),
+            sftp,
+            expected_checksum: expected_checksum.map(|c| c.to_ascii_lowercase()),
+            write_options,
+            verbose,
+        })
+    }
+}
+
+#[cfg(feature = "ssh")]
+impl HostsStore for SshStore {
+    fn name(&self) -> String {
+        format!("ssh://{}@{}:{}", self.user, self.host, self.path)
+    }
+    
+    fn check(&self) -> Result<()> {
+        let stat = self
+            .sftp
+            .stat(Path::new(&self.path))
+            .with_context(|| format!("hosts 文件不存在: {}", self.name()))?;
+        if !stat.is_file() {
+            anyhow::bail!("路径不是文件: {}", self.name());
+        }
+        Ok(())
+    }
+    
+    fn read(&self) -> Result<String> {
+        let mut content = String::new();
+        let mut file = self
+            .sftp
+            .open(Path::new(&self.path))
+            .with_context(|| format!("无法读取文件: {}", self.name()))?;
+        std::io::Read::read_to_string(&mut file, &mut content)
+            .with_context(|| format!("无法读取文件: {}", self.name()))?;
+        Ok(content)
+    }
+    
+    fn reader(&self) -> Result<Box<dyn BufRead>> {
+        Ok(Box::new(std::io::Cursor::new(self.read()?)))
+    }
+    
+    fn write(&self, content: &str) -> Result<()> {
+        if self.verbose {
+            eprintln!("[verbose] 写入 hosts 文件: {}", self.name());
+        }
+        
+        if let Some(expected) = &self.expected_checksum {
+            let current = sha256_hex(&self.read()?);
+            if current != *expected {
+                anyhow::bail!(
+                    "hosts 文件的 SHA-256 与 --expected-checksum 不一致，已放弃写入: {}（当前为 {}）",
+                    self.name(),
+                    current
+                );
+            }
+        }
+        
+        let prepared = self.write_options.prepare(content);
+        let content: &str = &prepared;
+        let mut file = self
+            .sftp
+            .create(Path::new(&self.path))
+            .with_context(|| format!("无法写入文件: {}", self.name()))?;
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("无法写入文件: {}", self.name()))
+    }
+    
+    fn backup(&self) -> Result<String> {
+        let timestamp = Local::now().format("%Y%m%d%H%M%S%3f");
+        let backup_path = format!("{}.{}.bak", self.path, timestamp);
+        
+        if self.verbose {
+            eprintln!("[verbose] 备份 hosts 文件到: {}", backup_path);
+        }
+        
+        let content = self.read()?;
+        let mut file = self
+            .sftp
+            .create(Path::new(&backup_path))
+            .with_context(|| format!("无法备份文件到: {}", backup_path))?;
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("无法备份文件到: {}", backup_path))?;
+        Ok(backup_path)
+    }
+}
+
+/// 写入前对内容做的整理
+#[derive(Clone, Copy)]
+struct WriteOptions {
+    /// 合并多余的空行
+    normalize_blanks: bool,
+    /// 按最长的 IP 对齐映射行
+    align: bool,
+    /// 末尾换行的处理方式
+    trailing_newline: TrailingNewline,
+    /// 将 IPv6 地址改写为规范形式
+    normalize_ipv6: bool,
+}
+
+impl WriteOptions {
+    fn prepare<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        let mut content = Cow::Borrowed(content);
+        // 先规范 IPv6 地址，对齐时才能按最终的长度计算宽度
+        if self.normalize_ipv6 {
+            content = Cow::Owned(normalize_ipv6_addresses(&content));
+        }
+        if self.normalize_blanks {
+            content = Cow::Owned(normalize_blank_lines(&content));
+        }
+        if self.align {
+            content = Cow::Owned(align_mapping_lines(&content));
+        }
+        match self.trailing_newline {
+            TrailingNewline::Keep => {}
+            TrailingNewline::Add if !content.is_empty() && !content.ends_with('\n') => {
+                // 沿用文件原有的换行风格
+                let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+                content.to_mut().push_str(newline);
+            }
+            TrailingNewline::Add => {}
+            TrailingNewline::Remove => {
+                let trimmed = content.trim_end_matches(['\r', '\n']).len();
+                if trimmed < content.len() {
+                    content.to_mut().truncate(trimmed);
+                }
+            }
+        }
+        content
+    }
+}
+
+/// 将映射行开头的 IPv6 地址改写为规范的小写压缩形式，保留 %接口 后缀和行内其他内容
+fn normalize_ipv6_addresses(content: &str) -> String {
+    let ip_regex = ip_regex();
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let ip = line.split_whitespace().next().unwrap_or("");
+        let (address, zone) = ip.split_once('%').map_or((ip, None), |(address, zone)| (address, Some(zone)));
+        match address.parse::<Ipv6Addr>() {
+            Ok(parsed) if ip_regex.is_match(line) => {
+                result.push_str(&parsed.to_string());
+                if let Some(zone) = zone {
+                    result.push('%');
+                    result.push_str(zone);
+                }
+                result.push_str(&line[ip.len()..]);
+            }
+            _ => result.push_str(line),
+        }
+    }
+    result
+}
+
+/// 将所有映射行的 IP 补齐到最长 IP 的宽度，使域名从同一列开始
+fn align_mapping_lines(content: &str) -> String {
+    let ip_regex = ip_regex();
+    let width = content
+        .lines()
+        .filter(|line| ip_regex.is_match(line))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        match body.split_once(char::is_whitespace) {
+            Some((ip, rest)) if ip_regex.is_match(body) => {
+                result.push_str(&format!("{:<w$} {}", ip, rest.trim_start(), w = width));
+                result.push_str(&line[body.len()..]);
+            }
+            _ => result.push_str(line),
+        }
+    }
+    result
+}
+
+/// 将连续的空行合并为一行，并去掉文件开头和末尾的空行；沿用文件的换行符，末尾原本没有换行时不追加
+fn normalize_blank_lines(content: &str) -> String {
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut result = String::with_capacity(content.len());
+    let mut pending_blank = false;
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            pending_blank = !result.is_empty();
+            continue;
+        }
+        if pending_blank {
+            result.push_str(newline);
+            pending_blank = false;
+        }
+        result.push_str(line);
+        result.push_str(newline);
+    }
+    if !content.ends_with('\n') && result.ends_with(newline) {
+        result.truncate(result.len() - newline.len());
+    }
+    result
+}
+
+/// 读取 hosts 文件，同时返回内容的 SHA-256（小写十六进制），用于检测并发修改
+fn read_hosts_with_checksum(path: &Path) -> Result<(String, String)> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("无法读取文件: {}", path.display()))?;
+    let checksum = sha256_hex(&content);
+    Ok((content, checksum))
+}
+
+/// Windows 记事本等工具保存的文件开头可能带有的 UTF-8 BOM
+const UTF8_BOM: &str = "\u{feff}";
+
+/// 跳过流开头的 UTF-8 BOM，否则第一行无法被识别为映射
+fn skip_bom(reader: &mut impl BufRead, path: &Path, verbose: bool) -> Result<()> {
+    let has_bom = reader
+        .fill_buf()
+        .with_context(|| format!("无法读取文件: {}", path.display()))?
+        .starts_with(UTF8_BOM.as_bytes());
+    if has_bom {
+        if verbose {
+            eprintln!("[verbose] ⚠️  {} 以 UTF-8 BOM 开头，已忽略", path.display());
+        }
+        reader.consume(UTF8_BOM.len());
+    }
+    Ok(())
+}
+
+/// 计算内容的 SHA-256，以小写十六进制表示
+fn sha256_hex(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// 通过内存映射读取的 hosts 文件内容
+#[cfg(feature = "mmap")]
+struct MmapContent(memmap2::Mmap);
+
+#[cfg(feature = "mmap")]
+impl AsRef<[u8]> for MmapContent {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// 以内存映射方式打开 hosts 文件，由操作系统按需换页加载
+#[cfg(feature = "mmap")]
+fn mmap_read_hosts(path: &Path) -> Result<MmapContent> {
+    let file = fs::File::open(path)?;
+    // SAFETY: 映射只读使用，hosts 文件在扫描期间被其他进程截断属于外部竞争，与 fs::read 的语义一致
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(MmapContent(mmap))
+}
+
+/// 检查 hosts 文件
+fn check_hosts_file(hosts_path: &Path, verbose: bool) -> Result<()> {
+    // 先解析符号链接（如 macOS 上 /etc/hosts -> /private/etc/hosts），再检查实际文件
+    let real_path = match fs::canonicalize(hosts_path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("hosts 文件不存在: {}", hosts_path.display());
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("无法访问文件: {}", hosts_path.display()));
+        }
+    };
+    
+    if verbose && real_path != hosts_path {
+        eprintln!("[verbose] hosts 文件实际路径: {}", real_path.display());
+    }
+    
+    if !real_path.is_file() {
+        anyhow::bail!("路径不是文件: {}", real_path.display());
+    }
+    
+    Ok(())
+}
+
+/// hosts 文件不存在时创建只含头部注释的文件；ssh:// 路径不处理
+fn create_missing_hosts_file(path: &Path, verbose: bool) -> Result<()> {
+    if path.to_string_lossy().starts_with("ssh://") || path.exists() {
+        return Ok(());
+    }
+    
+    let header = format!("# hosts file created by hostm {}\n", Local::now().format("%Y-%m-%d"));
+    if verbose {
+        eprintln!("[verbose] 创建 hosts 文件: {}", path.display());
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(header.as_bytes()))
+        .with_context(|| format!("无法创建 hosts 文件: {}", path.display()))?;
+    
+    eprintln!("📋 已创建 hosts 文件: {}", path.display());
     Ok(())
 }
 
+/// 粗略判断内容是否像 hosts 文件：去掉空行和注释后，至少一半的行以 IP 地址开头
+fn looks_like_hosts_file(content: &str) -> bool {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let mappings = lines
+        .iter()
+        .filter(|line| line.split_whitespace().next().is_some_and(|ip| ip.parse::<IpAddr>().is_ok()))
+        .count();
+    mappings * 2 >= lines.len()
+}
+
 /// 写入 hosts 文件
 fn write_hosts_file(hosts_path: &Path, content: &str, hosts_file: &str, verbose: bool) -> Result<()> {
     if verbose {
-        println!("[verbose] 写入 hosts 文件: {}", hosts_file);
+        eprintln!("[verbose] 写入 hosts 文件: {}", hosts_file);
     }
     
     match fs::write(hosts_path, content) {
         Ok(_) => Ok(()),
         Err(e) => {
+            if cfg!(windows) && e.kind() == std::io::ErrorKind::PermissionDenied {
+                return write_readonly_hosts_file(hosts_path, content, hosts_file, verbose);
+            }
+            
             if e.kind() == std::io::ErrorKind::PermissionDenied {
                 anyhow::bail!("权限不足，无法写入文件: {}", hosts_file);
             } else {
@@ -206,80 +5483,1147 @@ fn write_hosts_file(hosts_path: &Path, content: &str, hosts_file: &str, verbose:
     }
 }
 
-/// 更新已存在的域名映射
-fn update_existing_domain(content: &str, domain: &str, ip: &str, verbose: bool) -> Result<String> {
-    let ip_regex = Regex::new(r"^([0-9]+\.){3}[0-9]+[[:space:]]+")?;
-    let domain_regex = Regex::new(&format!(r"\b{}\b", regex::escape(domain)))?;
-    let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-    let mut domain_found = false;
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-    let comment = format!("# updated by hostm {}", timestamp);
-    
-    // 查找并更新现有域名
-    for line in &mut lines {
-        if ip_regex.is_match(line) && domain_regex.is_match(line) {
+/// Windows 上 hosts 文件常被设为只读，去掉只读属性后重试写入
+fn write_readonly_hosts_file(hosts_path: &Path, content: &str, hosts_file: &str, verbose: bool) -> Result<()> {
+    let mut permissions = fs::metadata(hosts_path)
+        .with_context(|| format!("无法读取文件属性: {}", hosts_file))?
+        .permissions();
+    if !clear_readonly(&mut permissions) {
+        anyhow::bail!("权限不足，无法写入文件: {}（请以管理员身份运行）", hosts_file);
+    }
+    
+    if verbose {
+        eprintln!("[verbose] 去掉只读属性: {}", hosts_file);
+    }
+    fs::set_permissions(hosts_path, permissions)
+        .with_context(|| format!("hosts 文件为只读，且无法去掉只读属性: {}（请以管理员身份运行）", hosts_file))?;
+    
+    fs::write(hosts_path, content).with_context(|| format!("无法写入文件: {}", hosts_file))
+}
+
+/// 去掉只读属性，原本就可写时返回 false；Unix 上只加回所有者的写权限，避免文件变为所有人可写
+fn clear_readonly(permissions: &mut fs::Permissions) -> bool {
+    if !permissions.readonly() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    {
+        // 非 Unix 平台只有只读属性，不涉及 world-writable 的问题
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+    }
+    true
+}
+
+/// 可以给出替代命令建议的错误
+#[derive(Debug)]
+enum HostsError {
+    /// 要更新的域名不存在，应改用 create
+    DomainNotFound { domain: String, ip: String },
+    /// 要创建的域名已存在，应改用 update
+    DomainExists { domain: String, ip: String },
+}
+
+impl HostsError {
+    /// 可直接复制执行的替代命令，hosts_args 为需要附带的 -f 参数
+    fn suggestion(&self, hosts_args: &str) -> String {
+        match self {
+            HostsError::DomainNotFound { domain, ip } => format!("hostm{} create {} {}", hosts_args, domain, ip),
+            HostsError::DomainExists { domain, ip } => format!("hostm{} update {} {}", hosts_args, domain, ip),
+        }
+    }
+}
+
+impl fmt::Display for HostsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostsError::DomainNotFound { domain, .. } => {
+                write!(f, "域名 '{}' 不存在，请使用 'create' 命令创建新映射", domain)
+            }
+            HostsError::DomainExists { domain, .. } => {
+                write!(f, "域名 '{}' 已存在，请使用 'update' 命令更新", domain)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HostsError {}
+
+/// 更新已存在的域名映射，target 为要更新的行下标，未指定时更新第一处匹配
+fn update_existing_domain(
+    content: &str,
+    domain: &str,
+    ip: &str,
+    comments: &Comments,
+    target: Option<usize>,
+    verbose: bool,
+) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    let comment = comments.render("updated")?;
+    
+    // 查找第一处匹配，只替换这一行，其余内容原样保留
+    let mut offset = 0;
+    for (line_idx, line) in content.split_inclusive('\n').enumerate() {
+        if target.is_none_or(|target| target == line_idx) && ip_regex.is_match(line) && domain_regex.is_match(line) {
+            let body = line.trim_end_matches(['\r', '\n']);
+            // 与 touch 相同，只替换 created/updated by hostm 注释，已验证标记、有效期等其他注释原样保留；
+            // 指定新标签时替换原有的分组标签
+            let (data, old_comments) = body.split_once('#').unwrap_or((body, ""));
+            let mut comment = comment;
+            for segment in old_comments.split('#').map(str::trim).filter(|segment| !segment.is_empty()) {
+                let replaced = managed_regex().is_match(&format!("# {}", segment))
+                    || (comments.tag.is_some() && tag_of(segment).is_some());
+                if !replaced {
+                    comment.push_str(&format!(" # {}", segment));
+                }
+            }
+            if let Some(tag) = &comments.tag {
+                comment.push_str(&format!(" # {}{}", TAG_MARKER, tag));
+            }
+            // 只替换 IP 字段，规范名和别名按原顺序保留
+            let names = data.trim().split_once(char::is_whitespace).map_or("", |(_, names)| names.trim());
+            let updated = format!("{} {} {}", ip, names, comment);
             if verbose {
-                println!("[verbose] 更新行: {} => {} {} {}", line, ip, domain, comment);
+                eprintln!("[verbose] 更新行: {} => {}", body, updated);
             }
-            *line = format!("{} {} {}", ip, domain, comment);
-            domain_found = true;
-            break;
+            let mut result = String::with_capacity(content.len() + comment.len());
+            result.push_str(&content[..offset]);
+            result.push_str(&updated);
+            result.push_str(&line[body.len()..]);
+            result.push_str(&content[offset + line.len()..]);
+            return Ok(result);
         }
+        offset += line.len();
+    }
+    
+    Err(HostsError::DomainNotFound {
+        domain: domain.to_string(),
+        ip: ip.to_string(),
     }
+    .into())
+}
+
+/// 将映射行中的 created/updated by hostm 注释替换为新的 updated 注释，IP、域名和其他注释（标签、有效期等）原样保留；
+/// target 为要修改的行下标，未指定时修改第一处匹配
+fn refresh_timestamp(content: &str, domain: &str, comments: &Comments, target: Option<usize>, verbose: bool) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    let comment = comments.render("updated")?;
     
-    if !domain_found {
-        anyhow::bail!("域名 '{}' 不存在，请使用 'create' 命令创建新映射", domain);
+    let mut offset = 0;
+    for (line_idx, line) in content.split_inclusive('\n').enumerate() {
+        if target.is_none_or(|target| target == line_idx) && ip_regex.is_match(line) && domain_regex.is_match(line) {
+            let body = line.trim_end_matches(['\r', '\n']);
+            let (data, old_comments) = body.split_once('#').unwrap_or((body, ""));
+            let mut refreshed = format!("{} {}", data.trim_end(), comment);
+            for segment in old_comments.split('#').map(str::trim).filter(|segment| !segment.is_empty()) {
+                if !managed_regex().is_match(&format!("# {}", segment)) {
+                    refreshed.push_str(&format!(" # {}", segment));
+                }
+            }
+            if verbose {
+                eprintln!("[verbose] 更新行: {} => {}", body, refreshed);
+            }
+            let mut result = String::with_capacity(content.len() + comment.len());
+            result.push_str(&content[..offset]);
+            result.push_str(&refreshed);
+            result.push_str(&line[body.len()..]);
+            result.push_str(&content[offset + line.len()..]);
+            return Ok(result);
+        }
+        offset += line.len();
     }
     
-    let result = lines.join("\n");
-    Ok(result + if content.ends_with('\n') { "\n" } else { "" })
+    anyhow::bail!("域名 '{}' 不存在，无法刷新时间戳", domain)
 }
 
-/// 删除域名映射
-fn remove_domain(content: &str, domain: &str, verbose: bool) -> Result<String> {
-    let ip_regex = Regex::new(r"^([0-9]+\.){3}[0-9]+[[:space:]]+")?;
-    let domain_regex = Regex::new(&format!(r"\b{}\b", regex::escape(domain)))?;
+/// 替换域名映射行的注释，target 为要修改的行下标，未指定时修改第一处匹配
+fn replace_comment(content: &str, domain: &str, comment: &str, target: Option<usize>, verbose: bool) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
     
+    let mut offset = 0;
+    for (line_idx, line) in content.split_inclusive('\n').enumerate() {
+        if target.is_none_or(|target| target == line_idx) && ip_regex.is_match(line) && domain_regex.is_match(line) {
+            let body = line.trim_end_matches(['\r', '\n']);
+            let data = body.split('#').next().unwrap_or(body).trim_end();
+            let replaced = format!("{} # {}", data, comment);
+            if verbose {
+                eprintln!("[verbose] 更新行: {} => {}", body, replaced);
+            }
+            let mut result = String::with_capacity(content.len() + comment.len());
+            result.push_str(&content[..offset]);
+            result.push_str(&replaced);
+            result.push_str(&line[body.len()..]);
+            result.push_str(&content[offset + line.len()..]);
+            return Ok(result);
+        }
+        offset += line.len();
+    }
+    
+    anyhow::bail!("域名 '{}' 不存在，无法更新注释", domain)
+}
+
+/// 从映射行中移除别名，规范名（第一个域名）不会被移除
+fn remove_alias(content: &str, alias: &str, canonical: Option<&str>, verbose: bool) -> Result<String> {
     let mut found = false;
-    let lines: Vec<&str> = content.lines()
-        .filter(|line| {
-            let matched = ip_regex.is_match(line) && domain_regex.is_match(line);
-            if verbose && matched {
-                println!("[verbose] 删除行: {}", line);
+    let mut is_canonical = false;
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let Some((ip, domains)) = parse_mapping(line) else {
+            result.push_str(line);
+            continue;
+        };
+        is_canonical |= domains[0] == alias;
+        if canonical.is_some_and(|c| c != domains[0]) || !domains[1..].contains(&alias) {
+            result.push_str(line);
+            continue;
+        }
+        
+        // 保留行内注释和原有的换行符
+        let body = line.trim_end_matches(['\r', '\n']);
+        let comment = body.find('#').map(|pos| format!(" {}", &body[pos..])).unwrap_or_default();
+        let remaining: Vec<&str> = domains.into_iter().filter(|d| *d != alias).collect();
+        let rewritten = format!("{} {}{}", ip, remaining.join(" "), comment);
+        if verbose {
+            eprintln!("[verbose] 更新行: {} => {}", body, rewritten);
+        }
+        result.push_str(&rewritten);
+        result.push_str(&line[body.len()..]);
+        found = true;
+    }
+    
+    if !found {
+        if is_canonical {
+            anyhow::bail!("'{}' 是规范名而不是别名，请使用 hostm delete {}", alias, alias);
+        }
+        match canonical {
+            Some(canonical) => anyhow::bail!("'{}' 不是 '{}' 的别名，无需删除", alias, canonical),
+            None => anyhow::bail!("别名 '{}' 不存在，无需删除", alias),
+        }
+    }
+    
+    Ok(result)
+}
+
+/// 删除域名映射，target 为要删除的行下标，未指定时删除所有匹配的行；没有匹配的行时返回 None
+fn remove_domain(content: &str, domain: &str, target: Option<usize>, verbose: bool) -> Result<Option<String>> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    
+    let mut found = false;
+    let result: String = content
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(line_idx, line)| {
+            let matched = target.is_none_or(|target| target == *line_idx)
+                && ip_regex.is_match(line)
+                && domain_regex.is_match(line);
+            if matched {
+                if verbose {
+                    eprintln!("[verbose] 删除行: {}", line.trim_end());
+                }
                 found = true;
             }
             !matched
         })
+        .map(|(_, line)| line)
         .collect();
     
+    Ok(found.then_some(result))
+}
+
+/// disable 加在行首的前缀，# 之后不留空格，以便与用户自己写的注释区分
+const DISABLED_PREFIX: &str = "#hostm:disabled ";
+
+/// disable 在行尾追加的注释标记
+const DISABLED_MARKER: &str = " # disabled by hostm ";
+
+/// 用 #hostm:disabled 前缀注释掉域名的映射行，并在行尾记录停用时间和原因
+fn disable_domain_lines(content: &str, domain: &str, reason: Option<&str>, verbose: bool) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    let mut annotation = format!("{}{}", DISABLED_MARKER, Local::now().format("%Y-%m-%d %H:%M:%S"));
+    if let Some(reason) = reason {
+        annotation.push_str(&format!(": {}", reason));
+    }
+    
+    let mut found = false;
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        if ip_regex.is_match(line) && domain_regex.is_match(line) {
+            let body = line.trim_end_matches(['\r', '\n']);
+            let disabled = format!("{}{}{}", DISABLED_PREFIX, body, annotation);
+            if verbose {
+                eprintln!("[verbose] 停用行: {} => {}", body, disabled);
+            }
+            result.push_str(&disabled);
+            result.push_str(&line[body.len()..]);
+            found = true;
+        } else {
+            result.push_str(line);
+        }
+    }
+    
+    if !found {
+        anyhow::bail!("域名 '{}' 不存在，无法停用", domain);
+    }
+    
+    Ok(result)
+}
+
+/// 恢复被 disable 注释掉的域名映射行，去掉行首前缀和行尾的停用标记；用户自己注释掉的行不受影响
+fn enable_domain_lines(content: &str, domain: &str, verbose: bool) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    
+    let mut found = false;
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        // 只认 disable 写下的前缀，用户自己注释掉的行即使形式相似也不处理
+        match body.strip_prefix(DISABLED_PREFIX) {
+            Some(original) if ip_regex.is_match(original) && domain_regex.is_match(original) => {
+                let restored = original.rfind(DISABLED_MARKER).map_or(original, |pos| &original[..pos]);
+                if verbose {
+                    eprintln!("[verbose] 恢复行: {} => {}", body, restored);
+                }
+                result.push_str(restored);
+                result.push_str(&line[body.len()..]);
+                found = true;
+            }
+            _ => result.push_str(line),
+        }
+    }
+    
     if !found {
-        anyhow::bail!("域名 '{}' 不存在，无需删除", domain);
+        anyhow::bail!("没有找到被 hostm disable 停用的域名 '{}'，无需恢复", domain);
     }
     
-    Ok(lines.join("\n") + if content.ends_with('\n') { "\n" } else { "" })
+    Ok(result)
+}
+
+/// 已验证映射在注释中的标记
+const TRUSTED_MARKER: &str = "hostm:trusted";
+
+/// 分组标签在注释中的前缀，完整形式为 hostm:tag=<标签>
+const TAG_MARKER: &str = "hostm:tag=";
+
+/// 从行内注释中取出分组标签
+fn tag_of(comment: &str) -> Option<&str> {
+    comment
+        .split(|c: char| c.is_whitespace() || c == '#')
+        .find_map(|token| token.strip_prefix(TAG_MARKER))
+        .filter(|tag| !tag.is_empty())
+}
+
+/// 新建映射时附加在注释中的信息
+#[derive(Default)]
+struct CreateOptions {
+    /// 映射的过期时间
+    expires: Option<DateTime<Utc>>,
+    /// 是否标记为已验证
+    trusted: bool,
+    /// 写在规范名之后的别名
+    aliases: Vec<String>,
+    /// 域名已存在时视为成功
+    exists_ok: bool,
+    /// 插入到该名称的 # BEGIN/# END 分区内
+    section: Option<String>,
+    /// 插入到该域名所在行的前后
+    anchor: Option<Anchor>,
+    /// 找不到锚点时的处理方式
+    anchor_fallback: AnchorFallback,
+}
+
+/// create 插入位置参照的域名
+struct Anchor {
+    domain: String,
+    /// 插入到锚点行之后，否则插入到之前
+    after: bool,
 }
 
 /// 添加新的域名映射
-fn add_new_domain(content: &str, domain: &str, ip: &str, verbose: bool) -> Result<String> {
-    let ip_regex = Regex::new(r"^([0-9]+\.){3}[0-9]+[[:space:]]+")?;
-    let domain_regex = Regex::new(&format!(r"\b{}\b", regex::escape(domain)))?;
-    let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-    let comment = format!("# created by hostm {}", timestamp);
+fn add_new_domain(
+    content: &str,
+    domain: &str,
+    ips: &[String],
+    options: &CreateOptions,
+    comments: &Comments,
+    verbose: bool,
+) -> Result<String> {
+    let ip_regex = ip_regex();
+    let domain_regex = domain_regex(domain)?;
+    let mut comment = comments.render("created")?;
+    if let Some(expires) = options.expires {
+        comment.push_str(&format!(" # hostm expires {}", expires.format(EXPIRES_FORMAT)));
+    }
+    if options.trusted {
+        comment.push_str(&format!(" # {}", TRUSTED_MARKER));
+    }
+    if let Some(tag) = &comments.tag {
+        comment.push_str(&format!(" # {}{}", TAG_MARKER, tag));
+    }
     
     // 检查域名是否已存在
-    for line in &lines {
+    for line in content.lines() {
         if ip_regex.is_match(line) && domain_regex.is_match(line) {
-            anyhow::bail!("域名 '{}' 已存在，请使用 'update' 命令更新", domain);
+            return Err(HostsError::DomainExists {
+                domain: domain.to_string(),
+                ip: ips.first().cloned().unwrap_or_default(),
+            }
+            .into());
         }
     }
     
-    // 添加新行
-    if verbose {
-        println!("[verbose] 添加新行: {} {} {}", ip, domain, comment);
+    // 每个 IP 一行，沿用原文件的换行符（Windows 上通常为 CRLF）
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let names: Vec<&str> = std::iter::once(domain).chain(options.aliases.iter().map(String::as_str)).collect();
+    let mut block = String::new();
+    for ip in ips {
+        if verbose {
+            eprintln!("[verbose] 添加新行: {} {} {}", ip, names.join(" "), comment);
+        }
+        block.push_str(&format!("{} {} {}{}", ip, names.join(" "), comment, newline));
+    }
+    
+    // 指定分区时插入到 # END 标记之前，分区不存在时在文件末尾新建
+    if let Some(section) = &options.section {
+        match find_section(content, section) {
+            Some((_, end)) => {
+                if verbose {
+                    eprintln!("[verbose] 插入到分区 {} 的末尾", section);
+                }
+                return Ok(format!("{}{}{}", &content[..end], block, &content[end..]));
+            }
+            None => {
+                if verbose {
+                    eprintln!("[verbose] 新建分区: {}", section);
+                }
+                block = format!("# BEGIN {}{}{}# END {}{}", section, newline, block, section, newline);
+            }
+        }
+    }
+    
+    // 指定锚点时插入到锚点行的前后
+    if let Some(anchor) = &options.anchor {
+        let anchor_regex = self::domain_regex(&anchor.domain)?;
+        let mut offset = 0;
+        for line in content.split_inclusive('\n') {
+            if ip_regex.is_match(line) && anchor_regex.is_match(line) {
+                if verbose {
+                    eprintln!("[verbose] 插入到 {} 所在行{}", anchor.domain, if anchor.after { "之后" } else { "之前" });
+                }
+                if !anchor.after {
+                    return Ok(format!("{}{}{}", &content[..offset], block, &content[offset..]));
+                }
+                let end = offset + line.len();
+                if line.ends_with('\n') {
+                    return Ok(format!("{}{}{}", &content[..end], block, &content[end..]));
+                }
+                // 锚点是没有换行符的最后一行，保持文件末尾同样没有换行符
+                return Ok(format!("{}{}{}", content, newline, block.trim_end_matches(['\r', '\n'])));
+            }
+            offset += line.len();
+        }
+        if options.anchor_fallback == AnchorFallback::Error {
+            anyhow::bail!("锚点域名 '{}' 不存在，可使用 --fallback end 追加到文件末尾", anchor.domain);
+        }
+        if verbose {
+            eprintln!("[verbose] 锚点域名 {} 不存在，追加到文件末尾", anchor.domain);
+        }
+    }
+    
+    // 在原内容之后追加
+    let mut result = content.to_string();
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push_str(newline);
+    }
+    result.push_str(&block);
+    if !content.ends_with('\n') {
+        result.truncate(result.len() - newline.len());
+    }
+    
+    Ok(result)
+}
+
+/// 查找 `# BEGIN <name>` 与 `# END <name>` 标记的分区，返回两行标记各自的起始字节位置
+fn find_section(content: &str, name: &str) -> Option<(usize, usize)> {
+    let begin_marker = format!("# BEGIN {}", name);
+    let end_marker = format!("# END {}", name);
+    let mut begin = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        match begin {
+            None if line.trim() == begin_marker => begin = Some(offset),
+            Some(begin) if line.trim() == end_marker => return Some((begin, offset)),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 只保存在内存中的存储后端，测试完整的命令流程时不触碰文件系统
+    struct MemoryStore {
+        content: RefCell<String>,
+        backups: RefCell<Vec<String>>,
+    }
+    
+    impl MemoryStore {
+        fn new(content: &str) -> Self {
+            MemoryStore { content: RefCell::new(content.to_string()), backups: RefCell::new(Vec::new()) }
+        }
+        
+        fn content(&self) -> String {
+            self.content.borrow().clone()
+        }
+    }
+    
+    impl HostsStore for MemoryStore {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+        
+        fn check(&self) -> Result<()> {
+            Ok(())
+        }
+        
+        fn read(&self) -> Result<String> {
+            Ok(self.content())
+        }
+        
+        fn reader(&self) -> Result<Box<dyn BufRead>> {
+            Ok(Box::new(std::io::Cursor::new(self.content().into_bytes())))
+        }
+        
+        fn write(&self, content: &str) -> Result<()> {
+            *self.content.borrow_mut() = content.to_string();
+            Ok(())
+        }
+        
+        fn backup(&self) -> Result<String> {
+            self.backups.borrow_mut().push(self.content());
+            Ok(format!("memory#{}", self.backups.borrow().len()))
+        }
+    }
+    
+    fn comments() -> Comments {
+        Comments { policy: CommentPolicy::Default, user: String::new(), tag: None }
+    }
+    
+    fn matching() -> MatchOptions {
+        MatchOptions { explain: false, select: None, missing_ok: false, expected_ip: None, plan: false }
+    }
+    
+    /// 去掉 hostm 注释中的时间戳，便于比较
+    fn without_timestamps(content: &str) -> String {
+        Regex::new(r" \d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}").unwrap().replace_all(content, "").into_owned()
+    }
+    
+    #[test]
+    fn create_update_delete_through_store() {
+        let store = MemoryStore::new("127.0.0.1 localhost\n");
+        
+        create_domain("api.local", &["10.0.0.1".to_string()], &CreateOptions::default(), &store, &comments(), false, false)
+            .unwrap();
+        assert_eq!(
+            without_timestamps(&store.content()),
+            "127.0.0.1 localhost\n10.0.0.1 api.local # created by hostm\n"
+        );
+        
+        update_domain("api.local", "10.0.0.2", &store, &comments(), &matching(), true, false).unwrap();
+        assert_eq!(
+            without_timestamps(&store.content()),
+            "127.0.0.1 localhost\n10.0.0.2 api.local # updated by hostm\n"
+        );
+        assert_eq!(store.backups.borrow().len(), 1);
+        
+        delete_domain("api.local", &store, &matching(), false, false).unwrap();
+        assert_eq!(store.content(), "127.0.0.1 localhost\n");
+    }
+    
+    #[test]
+    fn update_missing_domain_suggests_create() {
+        let store = MemoryStore::new("127.0.0.1 localhost\n");
+        let err = update_domain("api.local", "10.0.0.1", &store, &comments(), &matching(), false, false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<HostsError>(), Some(HostsError::DomainNotFound { .. })));
+        assert_eq!(store.content(), "127.0.0.1 localhost\n");
+    }
+    
+    #[test]
+    fn create_existing_domain_suggests_update() {
+        let store = MemoryStore::new("127.0.0.1 localhost\n10.0.0.1 api.local\n");
+        let err = create_domain("api.local", &["10.0.0.2".to_string()], &CreateOptions::default(), &store, &comments(), false, false)
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<HostsError>(), Some(HostsError::DomainExists { .. })));
+    }
+    
+    #[test]
+    fn conditional_flags_are_idempotent() {
+        let content = "127.0.0.1 localhost\n10.0.0.1 api.local\n";
+        let missing_ok = MatchOptions { missing_ok: true, ..matching() };
+        
+        // delete --if-exists：域名不存在时成功且不修改文件
+        let store = MemoryStore::new(content);
+        delete_domain("gone.local", &store, &missing_ok, false, false).unwrap();
+        assert_eq!(store.content(), content);
+        assert!(delete_domain("gone.local", &store, &matching(), false, false).is_err());
+        
+        // 只有“不存在”会被 --if-exists 忽略，其他错误照常报告
+        assert_eq!(remove_domain(content, "gone.local", None, false).unwrap(), None);
+        let huge = "a".repeat(2_000_000);
+        assert!(remove_domain(content, &huge, None, false).is_err());
+        let err = delete_domain(&huge, &store, &missing_ok, false, false).unwrap_err();
+        assert!(err.to_string().contains("大小限制"), "{}", err);
+        
+        // create --if-not-exists：域名已存在时成功且不修改文件
+        let options = CreateOptions { exists_ok: true, ..CreateOptions::default() };
+        create_domain("api.local", &["10.0.0.2".to_string()], &options, &store, &comments(), false, false).unwrap();
+        assert_eq!(store.content(), content);
+    }
+    
+    #[test]
+    fn completion_scripts_complete_existing_domains() {
+        for shell in [CompletionShell::Bash, CompletionShell::Zsh, CompletionShell::Fish] {
+            let script = completion_script(shell);
+            assert!(script.contains("__complete-domains"));
+            assert!(script.contains("/etc/hosts.d/*"));
+            assert!(script.contains("/tmp/hosts"));
+            for placeholder in ["@SUBCOMMANDS@", "@DOMAIN_COMMANDS@", "@VALUE_OPTIONS@", "@HOSTS_FILES@"] {
+                assert!(!script.contains(placeholder), "占位符未替换: {}", placeholder);
+            }
+        }
+        let bash = completion_script(CompletionShell::Bash);
+        assert!(bash.contains("update|delete|"));
+        assert!(!bash.contains("|create|"));
+        // 补全 -f 的值时提示路径，而不是子命令
+        assert!(bash.contains("-f|--hosts-file)\n"));
+        assert!(bash.contains("compgen -f -- \"$cur\""));
+        assert!(completion_script(CompletionShell::Zsh).contains("/etc/hosts.d/*(N)"));
+    }
+    
+    #[test]
+    fn export_is_canonical() {
+        let export = |name: &str, content: &str| {
+            let store = MemoryStore::new(content);
+            let path = std::env::temp_dir().join(format!("hostm-export-{}-{}", std::process::id(), name));
+            let mut out = Output::open(Some(&path), false).unwrap();
+            export_hosts(&[&store], ExportFormat::Hosts, false, &mut out, false).unwrap();
+            out.flush().unwrap();
+            drop(out);
+            let exported = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            exported
+        };
+        
+        let a = export("a", "2001:0db8::0001   api.local  WEB.local\n10.0.0.1\tdb.local\n");
+        let b = export("b", "10.0.0.1 db.local\n2001:db8::1 web.local\n2001:db8::1 api.local\n");
+        assert_eq!(a, b);
+        assert_eq!(a, "10.0.0.1 db.local\n2001:db8::1 api.local\n2001:db8::1 web.local\n");
+    }
+    
+    #[test]
+    fn apply_hunk_keeps_crlf_lines() {
+        let diff = "--- a/hosts\n+++ b/hosts\n@@ -2,2 +2,2 @@\n 10.0.0.1 a.local\n-10.0.0.2 b.local\n+10.0.0.3 b.local\n";
+        let content = "127.0.0.1 localhost\r\n10.0.0.1 a.local\r\n10.0.0.2 b.local\r\n10.0.0.4 c.local";
+        let ops: Vec<BatchOp> = parse_unified_diff(diff).unwrap().into_iter().map(|(_, op)| op.unwrap()).collect();
+        assert_eq!(
+            apply_ops(content, &ops, &comments(), false).unwrap(),
+            "127.0.0.1 localhost\r\n10.0.0.1 a.local\r\n10.0.0.3 b.local\r\n10.0.0.4 c.local"
+        );
+    }
+    
+    #[test]
+    fn apply_hunk_at_end_keeps_missing_newline() {
+        let diff = "@@ -1,1 +1,2 @@\n 127.0.0.1 localhost\n+10.0.0.1 a.local\n";
+        let ops: Vec<BatchOp> = parse_unified_diff(diff).unwrap().into_iter().map(|(_, op)| op.unwrap()).collect();
+        assert_eq!(apply_ops("127.0.0.1 localhost", &ops, &comments(), false).unwrap(), "127.0.0.1 localhost\n10.0.0.1 a.local");
+        assert_eq!(apply_ops("127.0.0.1 localhost\n", &ops, &comments(), false).unwrap(), "127.0.0.1 localhost\n10.0.0.1 a.local\n");
+    }
+    
+    #[test]
+    fn update_keeps_trusted_marker() {
+        let content = "10.0.0.1 api.local # created by hostm 2024-01-01 00:00:00 # hostm:trusted # hostm:tag=dev\n";
+        let updated = update_existing_domain(content, "api.local", "10.0.0.2", &comments(), None, false).unwrap();
+        assert_eq!(without_timestamps(&updated), "10.0.0.2 api.local # updated by hostm # hostm:trusted # hostm:tag=dev\n");
+        
+        let retagged = Comments { tag: Some("qa".to_string()), ..comments() };
+        let updated = update_existing_domain(content, "api.local", "10.0.0.2", &retagged, None, false).unwrap();
+        assert_eq!(without_timestamps(&updated), "10.0.0.2 api.local # updated by hostm # hostm:trusted # hostm:tag=qa\n");
+    }
+    
+    #[test]
+    fn update_keeps_expiry() {
+        let store = MemoryStore::new("10.0.0.1 tmp.local # created by hostm 2024-01-01 00:00:00 # hostm expires 2000-01-01T00:00:00Z\n");
+        update_domain("tmp.local", "10.0.0.2", &store, &comments(), &matching(), false, false).unwrap();
+        assert_eq!(
+            without_timestamps(&store.content()),
+            "10.0.0.2 tmp.local # updated by hostm # hostm expires 2000-01-01T00:00:00Z\n"
+        );
+        
+        // 更新后仍是会被 prune-expired 删除的临时映射
+        prune_expired(&store, false, false).unwrap();
+        assert_eq!(store.content(), "");
+    }
+    
+    #[test]
+    fn parse_duration_units_and_errors() {
+        assert_eq!(parse_duration("90s"), Ok(TimeDelta::seconds(90)));
+        assert_eq!(parse_duration("2h"), Ok(TimeDelta::hours(2)));
+        assert_eq!(parse_duration(" 7d "), Ok(TimeDelta::days(7)));
+        assert_eq!(parse_duration("1w"), Ok(TimeDelta::weeks(1)));
+        
+        assert!(parse_duration("5y").unwrap_err().contains("单位"));
+        assert!(parse_duration("5").unwrap_err().contains("单位"));
+        assert!(parse_duration("h").unwrap_err().contains("无效的时长"));
+        assert!(parse_duration("").unwrap_err().contains("无效的时长"));
+        assert!(parse_duration("-2h").unwrap_err().contains("无效的时长"));
+        // 数字超出 i64 与换算后超出 TimeDelta 范围都返回错误而不是溢出
+        assert!(parse_duration("99999999999999999999d").unwrap_err().contains("无效的时长"));
+        assert!(parse_duration("9223372036854775807w").unwrap_err().contains("时长过长"));
+    }
+    
+    #[test]
+    fn update_keeps_other_names_on_the_line() {
+        let content = "1.2.3.4 bar.local api.local web.local\n";
+        let updated = update_existing_domain(content, "api.local", "5.5.5.5", &comments(), None, false).unwrap();
+        assert_eq!(without_timestamps(&updated), "5.5.5.5 bar.local api.local web.local # updated by hostm\n");
+        
+        // 更新别名时规范名仍在第一位
+        let updated = update_existing_domain("1.2.3.4 x.local y.local\n", "y.local", "5.5.5.5", &comments(), None, false).unwrap();
+        assert_eq!(without_timestamps(&updated), "5.5.5.5 x.local y.local # updated by hostm\n");
+    }
+    
+    /// 100 万行屏蔽列表上主要改写操作的耗时；默认跳过，用 `cargo test --release -- --ignored --nocapture` 运行
+    #[test]
+    #[ignore]
+    fn bench_million_line_hosts_file() {
+        let mut content: String = (0..1_000_000).map(|i| format!("0.0.0.0 ads{}.example.com\n", i)).collect();
+        content.push_str("10.0.0.1 api.local\n");
+        
+        let time = |name: &str, run: &dyn Fn() -> String| {
+            let start = std::time::Instant::now();
+            let result = run();
+            println!("{:<8} {:>8.1?}", name, start.elapsed());
+            result
+        };
+        let updated = time("update", &|| update_existing_domain(&content, "api.local", "10.0.0.2", &comments(), None, false).unwrap());
+        assert!(updated.contains("\n10.0.0.2 api.local # updated by hostm "));
+        let removed = time("delete", &|| remove_domain(&content, "api.local", None, false).unwrap().unwrap());
+        assert_eq!(removed.len(), content.len() - "10.0.0.1 api.local\n".len());
+        let created = time("create", &|| {
+            add_new_domain(&content, "new.local", &["10.0.0.3".to_string()], &CreateOptions::default(), &comments(), false).unwrap()
+        });
+        assert!(created.len() > content.len());
+        time("find", &|| format!("{:?}", find_domain(&content, "api.local").map(|entry| entry.line)));
+    }
+    
+    fn write_options(normalize_blanks: bool) -> WriteOptions {
+        WriteOptions { normalize_blanks, align: false, trailing_newline: TrailingNewline::Keep, normalize_ipv6: false }
+    }
+    
+    #[test]
+    fn blank_lines_round_trip_on_noop_edit() {
+        for content in [
+            "127.0.0.1 localhost\n\n\n10.0.0.1 a.local\n\n\n",
+            "\n127.0.0.1 localhost\n\n10.0.0.1 a.local",
+            "127.0.0.1 localhost\r\n\r\n\r\n10.0.0.1 a.local\r\n\r\n",
+        ] {
+            // 不加 --normalize-blanks 时写入内容与原文件逐字节一致
+            assert_eq!(write_options(false).prepare(content), content);
+            // 更新映射只改动目标行，空行的位置保持不变
+            let updated = update_existing_domain(content, "a.local", "10.0.0.1", &comments(), None, false).unwrap();
+            let expected = content.replace("10.0.0.1 a.local", "10.0.0.1 a.local # updated by hostm");
+            assert_eq!(without_timestamps(&updated), expected);
+        }
+    }
+    
+    #[test]
+    fn normalize_blanks_keeps_line_endings() {
+        let normalize = |content: &str| write_options(true).prepare(content).into_owned();
+        assert_eq!(normalize("\n127.0.0.1 localhost\n\n\n10.0.0.1 a.local\n\n"), "127.0.0.1 localhost\n\n10.0.0.1 a.local\n");
+        assert_eq!(
+            normalize("127.0.0.1 localhost\r\n\r\n\r\n10.0.0.1 a.local\r\n\r\n"),
+            "127.0.0.1 localhost\r\n\r\n10.0.0.1 a.local\r\n"
+        );
+        assert_eq!(normalize("127.0.0.1 localhost\r\n\r\n\r\n10.0.0.1 a.local"), "127.0.0.1 localhost\r\n\r\n10.0.0.1 a.local");
+    }
+    
+    /// 在临时目录中创建测试用的 hosts 文件
+    fn temp_hosts(name: &str, content: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("hostm-test-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+    
+    #[test]
+    fn file_store_keeps_crlf_and_missing_final_newline() {
+        let path = temp_hosts("crlf", b"127.0.0.1 localhost\r\n10.0.0.1 a.local # \xe6\xb5\x8b\xe8\xaf\x95\r\n10.0.0.2 b.local");
+        let store = FileStore::new(&path, true, write_options(false), None, false);
+        
+        update_domain("b.local", "10.0.0.3", &store, &comments(), &matching(), false, false).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            without_timestamps(&written),
+            "127.0.0.1 localhost\r\n10.0.0.1 a.local # 测试\r\n10.0.0.3 b.local # updated by hostm"
+        );
+    }
+    
+    #[test]
+    fn file_store_strips_bom_but_checksums_raw_bytes() {
+        let raw = b"\xef\xbb\xbf127.0.0.1 localhost\n10.0.0.1 a.local\n";
+        let path = temp_hosts("bom", raw);
+        let store = FileStore::new(&path, true, write_options(false), None, false);
+        
+        assert_eq!(store.read().unwrap(), "127.0.0.1 localhost\n10.0.0.1 a.local\n");
+        assert_eq!(store.checksum().unwrap(), format!("{:x}", Sha256::digest(raw)));
+        
+        // 去掉 BOM 后第一行也能被识别为映射
+        update_domain("localhost", "127.0.0.1", &store, &comments(), &matching(), false, false).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(without_timestamps(&written), "127.0.0.1 localhost # updated by hostm\n10.0.0.1 a.local\n");
+    }
+    
+    #[cfg(windows)]
+    #[test]
+    fn write_clears_readonly_attribute() {
+        let path = temp_hosts("readonly", b"127.0.0.1 localhost\r\n");
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        
+        write_hosts_file(&path, "127.0.0.1 localhost\r\n10.0.0.1 a.local\r\n", "hosts", false).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "127.0.0.1 localhost\r\n10.0.0.1 a.local\r\n");
+    }
+    
+    #[test]
+    fn clear_readonly_only_touches_readonly_permissions() {
+        let path = temp_hosts("permissions", b"127.0.0.1 localhost\n");
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        fs::remove_file(&path).unwrap();
+        assert!(!clear_readonly(&mut permissions), "可写文件不需要处理");
+        
+        permissions.set_readonly(true);
+        assert!(clear_readonly(&mut permissions));
+        assert!(!permissions.readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(permissions.mode() & 0o022, 0, "不能变为组或其他用户可写");
+        }
+    }
+    
+    #[test]
+    fn invalid_pattern_is_a_clean_error() {
+        // 无效的正则只保留一行说明，不输出带插入符号的多行格式
+        let pattern = String::from("[");
+        let err = Regex::new(&pattern).unwrap_err();
+        let text = regex_error_text(&err);
+        assert!(!text.is_empty() && !text.contains('\n') && !text.contains('^'), "{}", text);
+        
+        // 超出编译大小限制时返回可读的错误而不是 panic
+        let huge = "a".repeat(2_000_000);
+        let err = domain_regex(&huge).unwrap_err().to_string();
+        assert!(err.starts_with("无法匹配域名") && err.contains("大小限制"), "{}", &err[err.len().saturating_sub(80)..]);
+        
+        // 域名中的正则元字符按字面匹配，找不到时报告域名不存在，而不是静默成功
+        let store = MemoryStore::new("127.0.0.1 localhost\n");
+        let err = update_domain("[", "10.0.0.1", &store, &comments(), &matching(), false, false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<HostsError>(), Some(HostsError::DomainNotFound { .. })));
+    }
+    
+    #[test]
+    fn similar_domain_suggests_close_names_only() {
+        let content = "127.0.0.1 localhost\n10.0.0.1 api.example.com www.example.com\n10.0.0.2 db.local\n";
+        assert_eq!(similar_domain(content, "api.exmaple.com").as_deref(), Some("api.example.com"));
+        assert_eq!(similar_domain(content, "db.locl").as_deref(), Some("db.local"));
+        // 完全相同或相差太远时不提示
+        assert_eq!(similar_domain(content, "db.local"), None);
+        assert_eq!(similar_domain(content, "cache.internal"), None);
+    }
+    
+    #[test]
+    fn cidr_contains_edges() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let net = parse_cidr("10.0.0.0/8").unwrap();
+        assert!(net.contains(&ip("10.255.255.255")));
+        assert!(!net.contains(&ip("11.0.0.0")));
+        
+        // /0 匹配同地址族的全部地址，/32 与省略前缀都只匹配单个地址
+        assert!(parse_cidr("0.0.0.0/0").unwrap().contains(&ip("203.0.113.7")));
+        assert!(parse_cidr("10.0.0.1/32").unwrap().contains(&ip("10.0.0.1")));
+        assert!(!parse_cidr("10.0.0.1/32").unwrap().contains(&ip("10.0.0.2")));
+        assert!(!parse_cidr("10.0.0.1").unwrap().contains(&ip("10.0.0.2")));
+        
+        assert!(parse_cidr("::/0").unwrap().contains(&ip("2001:db8::1")));
+        assert!(parse_cidr("2001:db8::/32").unwrap().contains(&ip("2001:db8:ffff::1")));
+        assert!(parse_cidr("2001:db8::1/128").unwrap().contains(&ip("2001:db8::1")));
+        assert!(!parse_cidr("2001:db8::1/128").unwrap().contains(&ip("2001:db8::2")));
+        
+        // 地址族不同时即使是 /0 也不匹配
+        assert!(!parse_cidr("0.0.0.0/0").unwrap().contains(&ip("::1")));
+        assert!(!parse_cidr("::/0").unwrap().contains(&ip("127.0.0.1")));
+        assert!(!net.contains_str("not-an-ip"));
+        
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("::/129").is_err());
+        assert!(parse_cidr("10.0.0.0/x").is_err());
+    }
+    
+    #[test]
+    fn disable_enable_round_trip() {
+        let content = "127.0.0.1 localhost\r\n10.0.0.1 api.local web.local # created by hostm 2024-01-01 00:00:00\r\n";
+        let disabled = disable_domain_lines(content, "api.local", Some("维护中"), false).unwrap();
+        let line = disabled.lines().nth(1).unwrap();
+        assert!(line.starts_with("#hostm:disabled 10.0.0.1 api.local web.local # created by hostm"), "{}", line);
+        assert!(line.ends_with(": 维护中"), "{}", line);
+        
+        assert_eq!(enable_domain_lines(&disabled, "api.local", false).unwrap(), content);
+    }
+    
+    #[test]
+    fn enable_leaves_user_comments_alone() {
+        // 用户自己注释掉的行，即使带有类似的标记也不恢复
+        let user = "# 10.0.0.1 api.local # disabled by hostm 2024-01-01 00:00:00\n";
+        assert!(enable_domain_lines(user, "api.local", false).is_err());
+        
+        let content = format!("{}#hostm:disabled 10.0.0.2 api.local # disabled by hostm 2024-01-01 00:00:00\n", user);
+        assert_eq!(enable_domain_lines(&content, "api.local", false).unwrap(), format!("{}10.0.0.2 api.local\n", user));
+    }
+    
+    #[test]
+    fn section_placement() {
+        let content = "127.0.0.1 localhost\n  # BEGIN docker  \n10.0.0.1 db.local\n# END docker\n# BEGIN dockerx\n# END dockerx\n";
+        let (begin, end) = find_section(content, "docker").unwrap();
+        assert_eq!(&content[begin..end], "  # BEGIN docker  \n10.0.0.1 db.local\n");
+        // 名称必须完全一致，缺少 END 标记时视为不存在
+        assert_eq!(find_section(content, "dock"), None);
+        assert_eq!(find_section("# BEGIN docker\n10.0.0.1 db.local\n", "docker"), None);
+        
+        let ip = ["10.0.0.2".to_string()];
+        let options = CreateOptions { section: Some("docker".to_string()), ..CreateOptions::default() };
+        let created = add_new_domain(content, "web.local", &ip, &options, &comments(), false).unwrap();
+        assert_eq!(
+            without_timestamps(&created),
+            "127.0.0.1 localhost\n  # BEGIN docker  \n10.0.0.1 db.local\n10.0.0.2 web.local # created by hostm\n# END docker\n# BEGIN dockerx\n# END dockerx\n"
+        );
+        
+        // 分区不存在时在文件末尾新建
+        let options = CreateOptions { section: Some("k8s".to_string()), ..CreateOptions::default() };
+        let created = add_new_domain("127.0.0.1 localhost\n", "web.local", &ip, &options, &comments(), false).unwrap();
+        assert_eq!(
+            without_timestamps(&created),
+            "127.0.0.1 localhost\n# BEGIN k8s\n10.0.0.2 web.local # created by hostm\n# END k8s\n"
+        );
     }
-    lines.push(format!("{} {} {}", ip, domain, comment));
     
-    let result = lines.join("\n");
-    Ok(result + if content.ends_with('\n') { "\n" } else { "" })
+    #[test]
+    fn anchor_placement() {
+        let content = "127.0.0.1 localhost\n10.0.0.1 api.local\n10.0.0.2 db.local";
+        let ip = ["10.0.0.9".to_string()];
+        let anchored = |domain: &str, after: bool, fallback: AnchorFallback| CreateOptions {
+            anchor: Some(Anchor { domain: domain.to_string(), after }),
+            anchor_fallback: fallback,
+            ..CreateOptions::default()
+        };
+        let create = |options: &CreateOptions| {
+            add_new_domain(content, "new.local", &ip, options, &comments(), false).map(|created| without_timestamps(&created))
+        };
+        
+        assert_eq!(
+            create(&anchored("api.local", false, AnchorFallback::Error)).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.9 new.local # created by hostm\n10.0.0.1 api.local\n10.0.0.2 db.local"
+        );
+        assert_eq!(
+            create(&anchored("api.local", true, AnchorFallback::Error)).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.1 api.local\n10.0.0.9 new.local # created by hostm\n10.0.0.2 db.local"
+        );
+        // 锚点是没有换行符的最后一行时，结果末尾同样没有换行符
+        assert_eq!(
+            create(&anchored("db.local", true, AnchorFallback::Error)).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.1 api.local\n10.0.0.2 db.local\n10.0.0.9 new.local # created by hostm"
+        );
+        
+        // 找不到锚点时默认报错，--fallback end 追加到末尾
+        assert!(create(&anchored("gone.local", true, AnchorFallback::Error)).is_err());
+        assert_eq!(
+            create(&anchored("gone.local", true, AnchorFallback::End)).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.1 api.local\n10.0.0.2 db.local\n10.0.0.9 new.local # created by hostm"
+        );
+    }
+    
+    #[test]
+    fn localhost_guard_blocks_only_localhost_loss() {
+        let content = "127.0.0.1 localhost\n::1 localhost ip6-localhost\n10.0.0.1 api.local\n";
+        let guarded = || LocalhostGuard { inner: Box::new(MemoryStore::new(content)), last_read: RefCell::new(None) };
+        
+        // 删除或改写 localhost 映射被拒绝，文件保持不变
+        let store = guarded();
+        assert!(delete_domain("localhost", &store, &matching(), false, false).is_err());
+        assert_eq!(store.read().unwrap(), content);
+        assert!(update_domain("localhost", "10.9.9.9", &store, &comments(), &matching(), false, false).is_err());
+        assert_eq!(store.read().unwrap(), content);
+        
+        // 删除同一行上的其他名称、修改其他映射或只改注释都允许
+        let store = guarded();
+        delete_domain("api.local", &store, &matching(), false, false).unwrap();
+        assert_eq!(store.read().unwrap(), "127.0.0.1 localhost\n::1 localhost ip6-localhost\n");
+        store.write("127.0.0.1   localhost # 系统\n::1 localhost\n").unwrap();
+        
+        assert_eq!(localhost_ip("127.0.0.1 localhost.localdomain"), None);
+        assert_eq!(localhost_ip("# 127.0.0.1 localhost"), None);
+        assert_eq!(localhost_ip("::1 ip6-localhost localhost"), Some("::1"));
+    }
+    
+    #[test]
+    fn manifest_renders_header_sections_and_order() {
+        let yaml = "\
+header: [由 infra/hosts.yaml 生成, '']
+order: domain
+entries:
+  - { ip: 127.0.0.1, domain: localhost }
+  - { ip: 10.0.0.9, domain: b.local }
+  - { ip: 10.0.0.8, domain: a.local, aliases: [www.a.local], comment: 主站 }
+sections:
+  - name: dev
+    comment: 开发环境
+    order: ip
+    entries:
+      - { ip: 10.0.1.2, domain: api.dev }
+      - { ip: 10.0.1.1, domain: 测试.dev }
+  - name: empty
+";
+        let expected = "\
+# 由 infra/hosts.yaml 生成
+#
+
+10.0.0.8 a.local www.a.local # 主站
+10.0.0.9 b.local
+127.0.0.1 localhost
+
+# BEGIN dev
+# 开发环境
+10.0.1.1 xn--0zwm56d.dev
+10.0.1.2 api.dev
+# END dev
+
+# BEGIN empty
+# END empty
+";
+        assert_eq!(Manifest::parse(yaml, true).unwrap().render().unwrap(), expected);
+        
+        // 同样的清单写成 JSON 结果一致
+        let json = r#"{
+            "header": ["由 infra/hosts.yaml 生成", ""],
+            "order": "domain",
+            "entries": [
+                { "ip": "127.0.0.1", "domain": "localhost" },
+                { "ip": "10.0.0.9", "domain": "b.local" },
+                { "ip": "10.0.0.8", "domain": "a.local", "aliases": ["www.a.local"], "comment": "主站" }
+            ],
+            "sections": [
+                { "name": "dev", "comment": "开发环境", "order": "ip", "entries": [
+                    { "ip": "10.0.1.2", "domain": "api.dev" },
+                    { "ip": "10.0.1.1", "domain": "测试.dev" }
+                ] },
+                { "name": "empty" }
+            ]
+        }"#;
+        assert_eq!(Manifest::parse(json, false).unwrap().render().unwrap(), expected);
+        
+        // 未知字段和无效 IP 都会报错
+        assert!(Manifest::parse("entries: []\nextra: 1\n", true).is_err());
+        assert!(Manifest::parse("entries:\n  - { ip: not-an-ip, domain: a.local }\n", true).is_err());
+        let bad_section = Manifest::parse("sections:\n  - name: ' '\n", true).unwrap();
+        assert!(bad_section.render().is_err());
+    }
+    
+    #[test]
+    fn ip_regex_rejects_ipv6_lookalikes() {
+        for line in ["10.0.0.1 a.local", "::1 localhost", "fe80::1%lo0 a.local", "2001:db8::1\ta.local", "::ffff:10.0.0.1 a.local"] {
+            assert!(ip_regex().is_match(line), "{}", line);
+        }
+        for line in ["cafe: api.local", ": api.local", "dead:beef: api.local", "999.0.0.1 api.local", "xx: api.local", "10.0.0.1"] {
+            assert!(!ip_regex().is_match(line), "{}", line);
+        }
+        
+        // 形似 IPv6 的普通文本不会被当成映射行改写
+        let content = "cafe: api.local\n10.0.0.1 api.local\n";
+        assert_eq!(remove_domain(content, "api.local", None, false).unwrap().unwrap(), "cafe: api.local\n");
+        let updated = update_existing_domain(content, "api.local", "10.0.0.2", &comments(), None, false).unwrap();
+        assert_eq!(without_timestamps(&updated), "cafe: api.local\n10.0.0.2 api.local # updated by hostm\n");
+    }
+    
+    #[test]
+    fn domains_match_whole_names_only() {
+        // ns1.internal 不会让 internal 被当成已存在
+        let store = MemoryStore::new("10.0.0.1 ns1.internal\n");
+        create_domain("internal", &["10.0.0.2".to_string()], &CreateOptions::default(), &store, &comments(), false, false).unwrap();
+        assert_eq!(without_timestamps(&store.content()), "10.0.0.1 ns1.internal\n10.0.0.2 internal # created by hostm\n");
+        
+        // 删除 internal 不会误删 internal-api
+        let store = MemoryStore::new("10.0.0.1 internal-api\n10.0.0.2 internal # 网关\n10.0.0.3 api.internal\n");
+        delete_domain("internal", &store, &matching(), false, false).unwrap();
+        assert_eq!(store.content(), "10.0.0.1 internal-api\n10.0.0.3 api.internal\n");
+        
+        let regex = domain_regex("internal").unwrap();
+        assert!(regex.is_match("10.0.0.2 internal\tx.local"));
+        assert!(regex.is_match("10.0.0.2 other internal#注释"));
+        assert!(!regex.is_match("10.0.0.2 internal.example"));
+        assert!(!regex.is_match("10.0.0.2 my_internal"));
+    }
+    
+    #[test]
+    fn parse_zone_expands_names_and_skips_other_records() {
+        let zone = "\
+$TTL 3600
+$ORIGIN example.com.
+@       IN SOA ns1 admin (
+            2024010101 ; serial
+            3600 )
+@       IN A     10.0.0.1
+www     300 IN A 10.0.0.2
+        IN AAAA  2001:db8::2
+mail    IN CNAME www
+        IN MX    10 mail
+api.example.com. A 10.0.0.3
+$ORIGIN dev.example.com.
+db      A 10.0.1.1
+*       A 10.0.1.9
+";
+        let records = parse_zone(zone, None, false).unwrap();
+        let found: Vec<(&str, String)> = records.iter().map(|record| (record.name.as_str(), record.ip.to_string())).collect();
+        assert_eq!(
+            found,
+            [
+                ("example.com", "10.0.0.1".to_string()),
+                ("www.example.com", "10.0.0.2".to_string()),
+                ("www.example.com", "2001:db8::2".to_string()),
+                ("api.example.com", "10.0.0.3".to_string()),
+                ("db.dev.example.com", "10.0.1.1".to_string()),
+            ]
+        );
+        
+        // --origin 在没有 $ORIGIN 时展开相对名称
+        let records = parse_zone("api A 10.0.0.1\n", Some("internal."), false).unwrap();
+        assert_eq!(records[0].name, "api.internal");
+        
+        assert!(parse_zone("api A not-an-ip\n", Some("internal."), false).is_err());
+        assert!(parse_zone("@ IN SOA ns1 admin ( 1\n", Some("internal."), false).is_err());
+    }
 }